@@ -0,0 +1,163 @@
+#![no_std]
+use soroban_sdk::{
+    contract, contractclient, contractimpl, contracttype, xdr::ToXdr, Address, BytesN, Env, Symbol, Vec,
+};
+
+mod test;
+
+/// The `VaquitaPool` entry points the factory calls on the instances it deploys.
+#[contractclient(name = "VaquitaPoolClient")]
+pub trait VaquitaPoolInterface {
+    fn initialize(env: Env, admin: Address, token: Address, pool_address: Address, lock_periods: Vec<u64>);
+    fn upgrade(env: Env, caller: Address, new_wasm_hash: BytesN<32>);
+    fn get_storage_version(env: Env) -> u32;
+}
+
+// ==================== STORAGE ====================
+
+#[derive(Clone)]
+#[contracttype]
+pub enum DataKey {
+    Admin,
+    WasmHash,
+    StorageVersion,
+    Pools,
+    TokenPools(Address),
+    PoolWasm(Address),
+}
+
+// ==================== CONTRACT ====================
+
+#[contract]
+pub struct VaquitaFactory;
+
+#[contractimpl]
+impl VaquitaFactory {
+    // ---------- Initialization ----------
+    pub fn initialize(env: Env, admin: Address, wasm_hash: BytesN<32>) {
+        if env.storage().instance().has(&DataKey::Admin) {
+            panic!("Already initialized");
+        }
+        env.storage().instance().set(&DataKey::Admin, &admin);
+        env.storage().instance().set(&DataKey::WasmHash, &wasm_hash);
+    }
+
+    // ---------- Owner Check ----------
+    fn require_owner(env: &Env, caller: Address) {
+        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+        if caller != admin {
+            panic!("Not owner");
+        }
+    }
+
+    // ---------- Owner functions ----------
+    /// Deploys a `VaquitaPool` for `token` supplying to the Blend pool `pool_address`
+    /// and initializes it in the same call, with the caller as its owner. The address
+    /// only depends on the token and how many pools were deployed for it before.
+    pub fn deploy_pool(env: Env, caller: Address, token: Address, pool_address: Address, lock_periods: Vec<u64>) -> Address {
+        caller.require_auth();
+        Self::require_owner(&env, caller.clone());
+        let wasm_hash: BytesN<32> = env.storage().instance().get(&DataKey::WasmHash).unwrap();
+        let mut token_pools = Self::token_pools(&env, &token);
+
+        let salt = Self::salt(&env, &token, token_pools.len());
+        let address = env.deployer().with_current_contract(salt).deploy_v2(wasm_hash.clone(), ());
+        VaquitaPoolClient::new(&env, &address).initialize(&caller, &token, &pool_address, &lock_periods);
+
+        token_pools.push_back(address.clone());
+        env.storage().instance().set(&DataKey::TokenPools(token.clone()), &token_pools);
+        let mut pools = Self::pools(&env);
+        pools.push_back(address.clone());
+        env.storage().instance().set(&DataKey::Pools, &pools);
+        env.storage().instance().set(&DataKey::PoolWasm(address.clone()), &wasm_hash);
+
+        env.events().publish(
+            (Symbol::new(&env, "pool_deployed"), token),
+            (address.clone(), pool_address, lock_periods),
+        );
+        address
+    }
+
+    /// Sets the `VaquitaPool` wasm new deployments use and `upgrade_pools` rolls out,
+    /// and the `STORAGE_VERSION` it was built with.
+    pub fn update_wasm_hash(env: Env, caller: Address, wasm_hash: BytesN<32>, storage_version: u32) {
+        caller.require_auth();
+        Self::require_owner(&env, caller);
+        env.storage().instance().set(&DataKey::WasmHash, &wasm_hash);
+        env.storage().instance().set(&DataKey::StorageVersion, &storage_version);
+        env.events().publish((Symbol::new(&env, "wasm_hash_updated"),), (wasm_hash, storage_version));
+    }
+
+    /// Upgrades at most `n` registered pools still running an older wasm, in the order
+    /// they were deployed. The caller must own the pools. Pools storing another layout
+    /// than the new wasm's are skipped, to be upgraded and migrated one by one. Returns
+    /// how many were upgraded.
+    pub fn upgrade_pools(env: Env, caller: Address, n: u32) -> u32 {
+        caller.require_auth();
+        Self::require_owner(&env, caller.clone());
+        let wasm_hash: BytesN<32> = env.storage().instance().get(&DataKey::WasmHash).unwrap();
+        let storage_version: u32 = env.storage().instance().get(&DataKey::StorageVersion).unwrap_or(0);
+        let mut upgraded = 0;
+        for pool in Self::pools(&env).iter() {
+            if upgraded == n {
+                break;
+            }
+            let pool_wasm: BytesN<32> = env.storage().instance().get(&DataKey::PoolWasm(pool.clone())).unwrap();
+            if pool_wasm == wasm_hash {
+                continue;
+            }
+            // Pools deployed before storage was versioned don't expose it
+            let client = VaquitaPoolClient::new(&env, &pool);
+            let pool_version = match client.try_get_storage_version() {
+                Ok(Ok(version)) => version,
+                _ => 0,
+            };
+            if pool_version != storage_version {
+                continue;
+            }
+            client.upgrade(&caller, &wasm_hash);
+            env.storage().instance().set(&DataKey::PoolWasm(pool.clone()), &wasm_hash);
+            env.events().publish((Symbol::new(&env, "pool_upgraded"), pool), wasm_hash.clone());
+            upgraded += 1;
+        }
+        upgraded
+    }
+
+    fn salt(env: &Env, token: &Address, index: u32) -> BytesN<32> {
+        env.crypto().sha256(&(token.clone(), index).to_xdr(env)).into()
+    }
+
+    fn pools(env: &Env) -> Vec<Address> {
+        env.storage().instance().get(&DataKey::Pools).unwrap_or(Vec::new(env))
+    }
+
+    fn token_pools(env: &Env, token: &Address) -> Vec<Address> {
+        env.storage().instance().get(&DataKey::TokenPools(token.clone())).unwrap_or(Vec::new(env))
+    }
+
+    // ---------- View functions ----------
+    pub fn get_wasm_hash(env: Env) -> BytesN<32> {
+        env.storage().instance().get(&DataKey::WasmHash).unwrap()
+    }
+
+    /// Every pool deployed by the factory, in deployment order.
+    pub fn get_pools(env: Env) -> Vec<Address> {
+        Self::pools(&env)
+    }
+
+    /// Pools deployed for `token`, in deployment order.
+    pub fn get_token_pools(env: Env, token: Address) -> Vec<Address> {
+        Self::token_pools(&env, &token)
+    }
+
+    /// Wasm hash the registered `pool` currently runs.
+    pub fn get_pool_wasm(env: Env, pool: Address) -> Option<BytesN<32>> {
+        env.storage().instance().get(&DataKey::PoolWasm(pool))
+    }
+
+    /// Address the `index`-th pool deployed for `token` gets.
+    pub fn get_deployed_address(env: Env, token: Address, index: u32) -> Address {
+        let salt = Self::salt(&env, &token, index);
+        env.deployer().with_current_contract(salt).deployed_address()
+    }
+}
@@ -0,0 +1,91 @@
+#![cfg(test)]
+use crate::{VaquitaFactory, VaquitaFactoryClient};
+use soroban_sdk::testutils::Address as _;
+use soroban_sdk::{Address, Bytes, BytesN, Env, Vec};
+
+mod vaquita_pool {
+    #![allow(clippy::too_many_arguments)]
+    soroban_sdk::contractimport!(file = "src/external_wasms/vaquita_pool.wasm");
+}
+
+fn setup(e: &Env) -> (Address, VaquitaFactoryClient<'_>) {
+    e.mock_all_auths();
+    let admin = Address::generate(e);
+    let factory = VaquitaFactoryClient::new(e, &e.register(VaquitaFactory, ()));
+    factory.initialize(&admin, &BytesN::from_array(e, &[1; 32]));
+    (admin, factory)
+}
+
+#[test]
+fn deployment_addresses_depend_on_token_and_index() {
+    let e = Env::default();
+    let (admin, factory) = setup(&e);
+    let usdc = Address::generate(&e);
+    let eurc = Address::generate(&e);
+
+    let first = factory.get_deployed_address(&usdc, &0);
+    assert_eq!(factory.get_deployed_address(&usdc, &0), first);
+    assert_ne!(factory.get_deployed_address(&usdc, &1), first);
+    assert_ne!(factory.get_deployed_address(&eurc, &0), first);
+    assert_eq!(factory.get_token_pools(&usdc), Vec::new(&e));
+    assert_eq!(factory.get_pools(), Vec::new(&e));
+
+    // Nothing is registered, so there is nothing to upgrade
+    factory.update_wasm_hash(&admin, &BytesN::from_array(&e, &[2; 32]), &1);
+    assert_eq!(factory.get_wasm_hash(), BytesN::from_array(&e, &[2; 32]));
+    assert_eq!(factory.upgrade_pools(&admin, &10), 0);
+}
+
+#[test]
+fn only_the_owner_manages_the_factory() {
+    let e = Env::default();
+    let (admin, factory) = setup(&e);
+    let stranger = Address::generate(&e);
+
+    assert!(factory.try_initialize(&stranger, &BytesN::from_array(&e, &[3; 32])).is_err());
+    assert!(factory.try_update_wasm_hash(&stranger, &BytesN::from_array(&e, &[3; 32]), &1).is_err());
+    assert!(factory.try_upgrade_pools(&stranger, &1).is_err());
+    let lock_periods = Vec::from_array(&e, [604800u64]);
+    assert!(factory.try_deploy_pool(&stranger, &Address::generate(&e), &Address::generate(&e), &lock_periods).is_err());
+    assert_eq!(factory.get_wasm_hash(), BytesN::from_array(&e, &[1; 32]));
+    assert_eq!(factory.get_pool_wasm(&admin), None);
+}
+
+#[test]
+fn deployed_pools_are_registered_and_upgraded_to_the_same_layout_only() {
+    let e = Env::default();
+    e.cost_estimate().budget().reset_unlimited();
+    e.mock_all_auths();
+    let admin = Address::generate(&e);
+    let usdc = Address::generate(&e);
+    let blend_pool = Address::generate(&e);
+    let wasm_hash = e.deployer().upload_contract_wasm(vaquita_pool::WASM);
+    let factory = VaquitaFactoryClient::new(&e, &e.register(VaquitaFactory, ()));
+    factory.initialize(&admin, &wasm_hash);
+
+    let lock_periods = Vec::from_array(&e, [604800u64]);
+    let address = factory.deploy_pool(&admin, &usdc, &blend_pool, &lock_periods);
+    assert_eq!(address, factory.get_deployed_address(&usdc, &0));
+    assert_eq!(factory.get_pools(), Vec::from_array(&e, [address.clone()]));
+    assert_eq!(factory.get_token_pools(&usdc), Vec::from_array(&e, [address.clone()]));
+    assert_eq!(factory.get_pool_wasm(&address), Some(wasm_hash.clone()));
+    let pool = vaquita_pool::Client::new(&e, &address);
+    assert_eq!(pool.get_tokens(), Vec::from_array(&e, [usdc.clone()]));
+    assert_eq!(pool.get_storage_version(), 1);
+
+    // A trailing custom section gives the same code another hash
+    let mut wasm = Bytes::from_slice(&e, vaquita_pool::WASM);
+    wasm.extend_from_array(&[0, 5, 4, b'n', b'e', b'x', b't']);
+    let next_hash = e.deployer().upload_contract_wasm(wasm);
+
+    // A wasm with another storage layout is not rolled out
+    factory.update_wasm_hash(&admin, &next_hash, &2);
+    assert_eq!(factory.upgrade_pools(&admin, &10), 0);
+    assert_eq!(factory.get_pool_wasm(&address), Some(wasm_hash));
+
+    factory.update_wasm_hash(&admin, &next_hash, &1);
+    assert_eq!(factory.upgrade_pools(&admin, &10), 1);
+    assert_eq!(factory.get_pool_wasm(&address), Some(next_hash));
+    assert_eq!(pool.get_tokens(), Vec::from_array(&e, [usdc]));
+    assert_eq!(factory.upgrade_pools(&admin, &10), 0);
+}
@@ -1,12 +1,66 @@
 #![no_std]
 use soroban_sdk::{
-    contract, contractimpl, contracttype, Address, Env, String, Vec, Symbol, token::Client as TokenClient
+    auth::{ContractContext, InvokerContractAuthEntry, SubContractInvocation},
+    contract, contracterror, contractimpl, contracttype, panic_with_error, vec, Address, Env, IntoVal,
+    BytesN, Map, String, Vec, Symbol, token::Client as TokenClient
 };
-soroban_sdk::contractimport!(file = "src/external_wasms/blend/pool.wasm");
+use soroban_fixed_point_math::SorobanFixedPoint;
+pub mod blend {
+    #![allow(clippy::too_many_arguments)]
+    soroban_sdk::contractimport!(file = "src/external_wasms/blend/pool.wasm");
+}
+pub use blend::*;
 pub type BlendPoolClient<'a> = Client<'a>;
+pub const SCALAR_7: i128 = 1_0000000;
 pub const SCALAR_12: i128 = 1_000_000_000_000;
+/// Delay between proposing a pool migration and executing it (3 days)
+pub const MIGRATION_TIMELOCK: u64 = 259200;
+/// Largest reward multiplier a position can get, in basis points (10x). Each
+/// configured multiplier is bounded by it and so is their product.
+pub const MAX_MULTIPLIER: u32 = 100_000;
+/// Layout of the stored types. Bumped whenever one of them changes, so upgrades to a
+/// wasm with another layout know the stored data needs migrating first.
+pub const STORAGE_VERSION: u32 = 1;
+
+// Rounding policy: every share, fee and reward computation rounds against the user
+// and in favor of the pool. Amounts paid out or credited to users (withdrawals,
+// rewards, accumulator increases) round down; amounts charged to users (fees, reward
+// debts) and protective minimums round up. The dust stays with the contract, so it
+// never owes more than it holds.
+
+pub mod comet {
+    soroban_sdk::contractimport!(file = "src/external_wasms/blend/comet.wasm");
+}
+pub type CometClient<'a> = comet::Client<'a>;
 
 mod test;
+mod yield_source;
+
+pub use yield_source::YieldSourceKind;
+use yield_source::{Adapter, YieldSource};
+
+// ==================== ERRORS ====================
+
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum VaquitaPoolError {
+    BRateTooHigh = 1,
+    InsufficientBTokens = 2,
+    InsufficientAmountOut = 3,
+    AllocationCapReached = 4,
+    PoolUnhealthy = 5,
+    ReserveDisabled = 6,
+    SupplyCapReached = 7,
+    PeriodCapReached = 8,
+    TvlCapReached = 9,
+    UserCapReached = 10,
+    DepositTooSmall = 11,
+    ZeroBTokens = 12,
+    DepositTooLarge = 13,
+    InvalidPeriod = 14,
+    InvalidOpenWindow = 15,
+}
 
 // ==================== DATA STRUCTS ====================
 
@@ -14,10 +68,26 @@ mod test;
 #[contracttype]
 pub struct Position {
     owner: Address,
+    token: Address,
     amount: i128,
     finalization_time: u64,
     lock_period: u64,
+    round: u32,
     b_rate: i128,
+    // bTokens minted by Blend for this position
+    b_tokens: i128,
+    reward_debt: i128,
+    // Reward debt in each bonus reward token
+    reward_debts: Map<Address, i128>,
+    emissions_debt: i128,
+    // Withdrawal queued until Blend can redeem it
+    frozen: bool,
+    // Pool migrations `b_tokens` has been rebased through
+    pool_epoch: u32,
+    // Allocation pool holding `b_tokens`, None for the configured yield source
+    pool: Option<Address>,
+    // Reward multiplier in basis points, fixed at deposit time
+    multiplier: u32,
 }
 
 #[derive(Clone)]
@@ -27,18 +97,264 @@ pub struct Period {
     total_deposits: i128,
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum LockPeriodStatus {
+    Active,
+    Disabled,
+}
+
+/// Terms of a lock period. Each deposit must be between `min_deposit` and
+/// `max_deposit`, and the period never holds more than `cap` of the deposit token.
+/// `bonus_multiplier` is in basis points.
+#[derive(Clone)]
+#[contracttype]
+pub struct LockPeriodConfig {
+    name: String,
+    min_deposit: i128,
+    max_deposit: i128,
+    cap: i128,
+    bonus_multiplier: u32,
+    status: LockPeriodStatus,
+}
+
+/// Reward boost for depositors holding at least `min_balance` of the partner `token`
+/// when they deposit. `multiplier` is in basis points. The balance is only checked at
+/// deposit time, so a partner token that can be flash-borrowed or moved between
+/// accounts lets anyone boost their deposits; only use tokens where that is acceptable.
+#[derive(Clone)]
+#[contracttype]
+pub struct PartnerBoost {
+    token: Address,
+    min_balance: i128,
+    multiplier: u32,
+}
+
+/// Launch limits on the deposits of a token: at most `tvl_cap` across every lock
+/// period, and at most `user_cap` held by a single depositor.
+#[derive(Clone)]
+#[contracttype]
+pub struct DepositCaps {
+    tvl_cap: i128,
+    user_cap: i128,
+}
+
+/// Amount that can still be deposited before reaching each cap, as returned by
+/// `get_remaining_capacity`.
+#[derive(Clone)]
+#[contracttype]
+pub struct DepositCapacity {
+    period: i128,
+    tvl: i128,
+    user: i128,
+}
+
+/// Entry of the lock period catalog returned by `get_lock_periods`.
+#[derive(Clone)]
+#[contracttype]
+pub struct LockPeriodInfo {
+    period: u64,
+    config: LockPeriodConfig,
+    data: Period,
+}
+
+/// Savings cycle of a lock period. Deposits join during the open window before
+/// `start_time` and every position of the round matures at `end_time`.
+#[derive(Clone)]
+#[contracttype]
+pub struct Round {
+    start_time: u64,
+    end_time: u64,
+    reward_pool: i128,
+    total_deposits: i128,
+    // Deposits weighted by their positions' multipliers, which rewards are split by
+    total_shares: i128,
+    // Rewards accrued per share after the round started, scaled by SCALAR_12
+    reward_per_share: i128,
+    // Rewards waiting to be split between the round's depositors once it starts
+    undistributed_rewards: i128,
+}
+
+/// Round pot and accumulator of a bonus reward token, same rules as the deposit
+/// token rewards kept in `Round`.
+#[derive(Clone)]
+#[contracttype]
+pub struct RewardIndex {
+    reward_pool: i128,
+    reward_per_share: i128,
+    undistributed_rewards: i128,
+}
+
+/// Rewards released linearly between `start_time` and `end_time`.
+#[derive(Clone)]
+#[contracttype]
+pub struct RewardStream {
+    amount: i128,
+    start_time: u64,
+    end_time: u64,
+}
+
+/// Swaps harvested BLND into the deposit token through a Comet pool. `min_price`
+/// is the least amount of deposit token accepted per BLND, scaled by SCALAR_7.
+#[derive(Clone)]
+#[contracttype]
+pub struct CompoundingConfig {
+    comet: Address,
+    min_price: i128,
+}
+
+/// Withdrawal settled while Blend lacked the liquidity to redeem it. `payout` is
+/// paid once `redeem_amount` is withdrawn from the position's bTokens.
+#[derive(Clone)]
+#[contracttype]
+pub struct QueuedWithdrawal {
+    deposit_id: String,
+    owner: Address,
+    pool: Option<Address>,
+    lock_period: u64,
+    round: u32,
+    b_tokens: i128,
+    redeem_amount: i128,
+    payout: i128,
+    // Early withdrawal fee and forfeited interest, credited once redeemed
+    protocol_fee: i128,
+    insured: i128,
+    forfeit: i128,
+    loss: i128,
+    reward: i128,
+    // Redeemed, and only waiting for the insurance reserve to cover `loss`
+    redeemed: bool,
+}
+
+/// Blend pool deposits are split into, aiming for `weight` of the total and never
+/// holding more than `cap` of the deposit token.
+#[derive(Clone)]
+#[contracttype]
+pub struct PoolAllocation {
+    pool: Address,
+    weight: u32,
+    cap: i128,
+}
+
+/// Limits on `rebalance`: at most `max_amount` of the deposit token moved per call,
+/// out of the `max_positions` oldest positions of the pool, only toward a pool paying
+/// at least `min_rate_gap` more (scaled by SCALAR_7), and `keeper_fee` bps of the
+/// amount moved paid to the caller out of protocol fees.
+#[derive(Clone)]
+#[contracttype]
+pub struct RebalanceConfig {
+    max_amount: i128,
+    max_positions: u32,
+    min_rate_gap: i128,
+    keeper_fee: i128,
+}
+
+/// Pool migration waiting for its timelock to expire.
+#[derive(Clone)]
+#[contracttype]
+pub struct PendingMigration {
+    new_pool: Address,
+    executable_at: u64,
+}
+
+/// bTokens held in the old pool and minted by the new one when migrating, which
+/// rebases the positions minted before it.
+#[derive(Clone)]
+#[contracttype]
+pub struct PoolMigration {
+    b_tokens_before: i128,
+    b_tokens_after: i128,
+}
+
+/// bTokens of an allocation pool emptied by `emergency_exit` and what the configured
+/// yield source minted for them at `pool_epoch`, which rebases the pool's positions.
+#[derive(Clone)]
+#[contracttype]
+pub struct PoolExit {
+    b_tokens_before: i128,
+    b_tokens_after: i128,
+    pool_epoch: u32,
+}
+
+/// Outcome of withdrawing a position, as returned by `preview_withdraw`.
+#[derive(Clone)]
+#[contracttype]
+pub struct WithdrawPreview {
+    principal: i128,
+    interest: i128,
+    early_fee: i128,
+    forfeited_interest: i128,
+    reward: i128,
+    bonus_rewards: Map<Address, i128>,
+    emissions: i128,
+    payout: i128,
+}
+
+/// Outcome of a deposit, as returned by `preview_deposit`.
+#[derive(Clone)]
+#[contracttype]
+pub struct DepositPreview {
+    b_tokens: i128,
+    round: u32,
+    maturity: u64,
+}
+
+/// Rounds of a lock period run back to back from `first_start`, each one
+/// accepting deposits during the `open_window` seconds before it starts.
+#[derive(Clone)]
+#[contracttype]
+pub struct RoundSchedule {
+    first_start: u64,
+    open_window: u64,
+}
+
 #[derive(Clone)]
 #[contracttype]
 pub enum DataKey {
     Admin,
-    Token,
-    PoolAddress,
+    Tokens,
+    PoolAddress(Address),
     BasisPoints,
-    EarlyWithdrawalFee,
-    ProtocolFees,
+    EarlyWithdrawalFee(Address),
+    ProtocolFees(Address),
     Positions(String),
-    Periods(u64),
-    SupportedLockPeriod(u64),
+    Periods(Address, u64),
+    LockPeriodConfigs(Address, u64),
+    DepositCaps(Address),
+    UserDeposits(Address, Address),
+    UserMultiplier(Address),
+    PartnerBoost,
+    RoundSchedules(Address, u64),
+    Rounds(Address, u64, u32),
+    RewardStreams(Address, u64),
+    StreamsAccruedAt(Address, u64),
+    RewardTokens,
+    RoundRewards(Address, u64, u32, Address),
+    TotalDeposits(Address),
+    BlndToken,
+    EmissionsDestination,
+    EmissionsPerShare(Address),
+    UndistributedEmissions(Address),
+    CompoundingConfig(Address),
+    ProtocolBTokens(Address),
+    InsuranceBTokens(Address),
+    InsuranceFeeShare(Address),
+    InsuranceForfeitShare(Address),
+    WithdrawalQueue(Address),
+    YieldSource(Address),
+    VaultShares(Address),
+    PendingMigration(Address),
+    PoolEpoch(Address),
+    PoolMigrations(Address, u32),
+    PoolAllocations(Address),
+    PoolBTokens(Address, Address),
+    PoolPositions(Address, Address),
+    RebalanceConfig(Address),
+    TotalBTokens(Address),
+    LockPeriods(Address),
+    PoolExits(Address, Address),
+    DepositsHalted(Address),
+    StorageVersion,
 }
 
 // ==================== CONTRACT ====================
@@ -49,20 +365,56 @@ pub struct VaquitaPool;
 #[contractimpl]
 impl VaquitaPool {
     // ---------- Initialization ----------
+    /// Sets the owner and registers the first deposit token.
     pub fn initialize(env: Env, admin: Address, token: Address, pool_address: Address, lock_periods: Vec<u64>) {
         if env.storage().instance().has(&DataKey::Admin) {
             panic!("Already initialized");
         }
         env.storage().instance().set(&DataKey::Admin, &admin);
-        env.storage().instance().set(&DataKey::Token, &token);
-        env.storage().instance().set(&DataKey::PoolAddress, &pool_address);
         env.storage().instance().set(&DataKey::BasisPoints, &10000i128);
-        env.storage().instance().set(&DataKey::EarlyWithdrawalFee, &0i128);
-        env.storage().instance().set(&DataKey::ProtocolFees, &0i128);
+        env.storage().instance().set(&DataKey::StorageVersion, &STORAGE_VERSION);
+        Self::register_token(&env, &token, &pool_address, &lock_periods);
+    }
+
+    /// Supports deposits of `token`, supplied to the Blend pool `pool_address`, with
+    /// their own lock periods, fees and reward pools.
+    pub fn add_token(env: Env, caller: Address, token: Address, pool_address: Address, lock_periods: Vec<u64>) {
+        caller.require_auth();
+        Self::require_owner(&env, caller);
+        if Self::tokens(&env).contains(&token) {
+            panic!("Token already supported");
+        }
+        if Self::reward_tokens(&env).contains(&token) {
+            panic!("Token is a reward token");
+        }
+        Self::register_token(&env, &token, &pool_address, &lock_periods);
+        env.events().publish(
+            (Symbol::new(&env, "token_added"), token),
+            (pool_address, lock_periods),
+        );
+    }
+
+    fn register_token(env: &Env, token: &Address, pool_address: &Address, lock_periods: &Vec<u64>) {
+        env.storage().instance().set(&DataKey::PoolAddress(token.clone()), pool_address);
+        env.storage().instance().set(&DataKey::EarlyWithdrawalFee(token.clone()), &0i128);
+        env.storage().instance().set(&DataKey::ProtocolFees(token.clone()), &0i128);
 
         for lp in lock_periods.iter() {
-            env.storage().instance().set(&DataKey::SupportedLockPeriod(lp.clone()), &true);
+            if env.storage().instance().has(&DataKey::LockPeriodConfigs(token.clone(), lp)) {
+                panic!("Lock period already supported");
+            }
+            env.storage().instance().set(&DataKey::LockPeriodConfigs(token.clone(), lp), &Self::default_lock_period(env));
+            Self::schedule_rounds(env, token, lp);
         }
+        env.storage().instance().set(&DataKey::LockPeriods(token.clone()), lock_periods);
+
+        let mut tokens = Self::tokens(env);
+        tokens.push_back(token.clone());
+        env.storage().instance().set(&DataKey::Tokens, &tokens);
+    }
+
+    fn tokens(env: &Env) -> Vec<Address> {
+        env.storage().instance().get(&DataKey::Tokens).unwrap_or(Vec::new(env))
     }
 
     // ---------- Owner Check ----------
@@ -74,7 +426,23 @@ impl VaquitaPool {
     }
 
     // ---------- Deposit ----------
-    pub fn deposit(env: Env, caller: Address, deposit_id: String, amount: i128, period: u64) {
+    pub fn deposit(env: Env, caller: Address, token: Address, deposit_id: String, amount: i128, period: u64) {
+        Self::deposit_with_limits(env, caller, token, deposit_id, amount, period, i128::MAX, 0);
+    }
+
+    /// Deposits like `deposit`, failing when Blend's bToken rate is above `max_b_rate`
+    /// or the supply mints fewer than `min_b_tokens`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn deposit_with_limits(
+        env: Env,
+        caller: Address,
+        token: Address,
+        deposit_id: String,
+        amount: i128,
+        period: u64,
+        max_b_rate: i128,
+        min_b_tokens: i128,
+    ) {
         caller.require_auth();
     
         if amount <= 0 {
@@ -83,220 +451,2145 @@ impl VaquitaPool {
         if env.storage().instance().has(&DataKey::Positions(deposit_id.clone())) {
             panic!("Deposit already exists");
         }
-        let supported: bool = env.storage().instance()
-            .get(&DataKey::SupportedLockPeriod(period))
-            .unwrap_or(false);
-        if !supported {
-            panic!("Invalid period");
+        let lock_period = Self::active_lock_period(&env, &token, period);
+        // Deposits wait for the owner to pick a healthy source after an emergency exit
+        if env.storage().instance().has(&DataKey::DepositsHalted(token.clone())) {
+            panic_with_error!(&env, VaquitaPoolError::PoolUnhealthy);
+        }
+        if amount < lock_period.min_deposit {
+            panic_with_error!(&env, VaquitaPoolError::DepositTooSmall);
+        }
+        if amount > lock_period.max_deposit {
+            panic_with_error!(&env, VaquitaPoolError::DepositTooLarge);
+        }
+        let capacity = Self::deposit_capacity(&env, &token, period, &caller);
+        if amount > capacity.period {
+            panic_with_error!(&env, VaquitaPoolError::PeriodCapReached);
+        }
+        if amount > capacity.tvl {
+            panic_with_error!(&env, VaquitaPoolError::TvlCapReached);
+        }
+        if amount > capacity.user {
+            panic_with_error!(&env, VaquitaPoolError::UserCapReached);
         }
     
-        let token: Address = env.storage().instance().get(&DataKey::Token).unwrap();
-        let pool_address: Address = env.storage().instance().get(&DataKey::PoolAddress).unwrap();
         let contract_address = env.current_contract_address();
-        let current_ledger = env.ledger().sequence();
-        let finalization_time = env.ledger().timestamp() + period;
+        let now = env.ledger().timestamp();
+
+        // Harvest the emissions earned so far before the position joins. Compounding
+        // credits the rounds, so they are loaded afterwards.
+        Self::harvest(&env, &token);
+
+        // Deposits join the next round and mature together with it
+        let round_id = Self::next_round(&env, &token, period, now);
+        let mut round = Self::load_round(&env, &token, period, round_id);
+        let schedule = Self::load_round_schedule(&env, &token, period);
+        if now < round.start_time - schedule.open_window {
+            panic!("Round not open");
+        }
     
         // Step 1: Pull tokens from user
         let token_client = TokenClient::new(&env, &token);
         token_client.transfer(&caller, &contract_address, &amount);
     
-        // Step 2: Approve pool to spend from contract
-        token_client.approve(
-            &contract_address,
-            &pool_address,
-            &amount,
-            &(current_ledger),
-        );
-    
-        // Step 3: Track deposits (simplified)
+        // Step 2: Track deposits (simplified)
     
         let mut position = Position {
             owner: caller.clone(),
+            token: token.clone(),
             amount,
-            finalization_time,
+            finalization_time: round.end_time,
             lock_period: period,
+            round: round_id,
             b_rate: 0,
+            b_tokens: 0,
+            reward_debt: 0,
+            reward_debts: Map::new(&env),
+            emissions_debt: 0,
+            frozen: false,
+            pool_epoch: env.storage().instance().get(&DataKey::PoolEpoch(token.clone())).unwrap_or(0),
+            pool: Self::route_deposit(&env, &token, amount),
+            multiplier: Self::reward_multiplier(&env, &caller, &lock_period),
         };
 
-        // Step 5: Supply to Blend on contract’s behalf
-        let request = Request { 
-            request_type: 0u32, // Supply
-            address: token.clone(),
-            amount,
+        // Step 3: Supply to the yield source on contract’s behalf
+        let yield_source = Adapter::for_pool(&env, &token, &position.pool);
+        if let Err(error) = yield_source.check_supply(&env, amount) {
+            panic_with_error!(&env, error);
+        }
+        let b_rate = yield_source.rate(&env);
+        if b_rate > max_b_rate {
+            panic_with_error!(&env, VaquitaPoolError::BRateTooHigh);
+        }
+        // A position without bTokens would never earn and could not be redeemed
+        if amount.fixed_div_floor(&env, &b_rate, &SCALAR_12) == 0 {
+            panic_with_error!(&env, VaquitaPoolError::ZeroBTokens);
+        }
+
+        // Record exactly what was minted rather than re-deriving it from the rate, and
+        // the rate the position actually paid for it
+        position.b_tokens = yield_source.supply(&env, amount);
+        if position.b_tokens < min_b_tokens {
+            panic_with_error!(&env, VaquitaPoolError::InsufficientBTokens);
+        }
+        position.b_rate = amount.fixed_div_ceil(&env, &position.b_tokens, &SCALAR_12);
+        let total_key = match &position.pool {
+            Some(pool) => {
+                let mut pool_positions = Self::pool_positions(&env, &token, pool);
+                pool_positions.push_back(deposit_id.clone());
+                env.storage().instance().set(&DataKey::PoolPositions(token.clone(), pool.clone()), &pool_positions);
+                DataKey::PoolBTokens(token.clone(), pool.clone())
+            }
+            None => DataKey::TotalBTokens(token.clone()),
         };
-        let requests = Vec::from_array(&env, [request]);
-        let pool_client = BlendPoolClient::new(&env, &pool_address);
-        pool_client.submit_with_allowance(&contract_address, &contract_address, &contract_address, &requests);
+        let total_b_tokens: i128 = env.storage().instance().get(&total_key).unwrap_or(0);
+        env.storage().instance().set(&total_key, &(total_b_tokens + position.b_tokens));
 
-        let b_rate = pool_client.get_reserve(&token).data.b_rate;
-        position.b_rate = b_rate;
+        // Step 4: Update total deposits for this period and round. The position only
+        // earns rewards accrued from now on, so its debt starts at the current accumulator.
+        let shares = Self::shares(&env, &position);
+        position.reward_debt = shares.fixed_mul_ceil(&env, &round.reward_per_share, &SCALAR_12);
+        for reward_token in Self::reward_tokens(&env).iter() {
+            let index = Self::load_round_rewards(&env, &token, period, round_id, &reward_token);
+            position.reward_debts.set(reward_token, shares.fixed_mul_ceil(&env, &index.reward_per_share, &SCALAR_12));
+        }
+        round.total_deposits += amount;
+        round.total_shares += shares;
+        env.storage().instance().set(&DataKey::Rounds(token.clone(), period, round_id), &round);
 
-        env.storage().instance().set(&DataKey::Positions(deposit_id.clone()), &position);
-    
-        // Step 6: Update total deposits for this period
-        let mut period_data: Period = env.storage().instance()
-            .get(&DataKey::Periods(period))
-            .unwrap_or(Period { reward_pool: 0, total_deposits: 0 });
+        let mut period_data = Self::load_period(&env, &token, period);
         period_data.total_deposits += amount;
-        env.storage().instance().set(&DataKey::Periods(period), &period_data);
+        env.storage().instance().set(&DataKey::Periods(token.clone(), period), &period_data);
+
+        let emissions_per_share: i128 = env.storage().instance().get(&DataKey::EmissionsPerShare(token.clone())).unwrap_or(0);
+        position.emissions_debt = amount.fixed_mul_ceil(&env, &emissions_per_share, &SCALAR_12);
+        let total_deposits: i128 = env.storage().instance().get(&DataKey::TotalDeposits(token.clone())).unwrap_or(0);
+        env.storage().instance().set(&DataKey::TotalDeposits(token.clone()), &(total_deposits + amount));
+        let user_deposits = Self::user_deposits(&env, &token, &caller);
+        env.storage().instance().set(&DataKey::UserDeposits(token.clone(), caller.clone()), &(user_deposits + amount));
 
-        // Step 7: Emit event
+        env.storage().instance().set(&DataKey::Positions(deposit_id.clone()), &position);
+
+        // Step 5: Emit event
         env.events().publish(
             (Symbol::new(&env, "deposit"), caller),
-            (deposit_id, token, amount, b_rate),
+            (deposit_id, token, amount, position.b_rate, round_id),
         );
     }
 
     // ---------- Withdraw ----------
-    pub fn withdraw(env: Env, caller: Address, deposit_id: String) {
+    pub fn withdraw(env: Env, caller: Address, token: Address, deposit_id: String) {
+        Self::withdraw_with_min_amount(env, caller, token, deposit_id, 0);
+    }
+
+    /// Withdraws like `withdraw`, failing when the position would pay out less than
+    /// `min_amount_out` of the deposit token. A queued withdrawal is checked against
+    /// its settled payout.
+    pub fn withdraw_with_min_amount(env: Env, caller: Address, token: Address, deposit_id: String, min_amount_out: i128) {
         caller.require_auth();
         
-        let position: Position = env.storage().instance().get(&DataKey::Positions(deposit_id.clone()))
+        let mut position: Position = env.storage().instance().get(&DataKey::Positions(deposit_id.clone()))
             .unwrap_or_else(|| panic!("Position not found"));
 
         if caller != position.owner {
             panic!("Not position owner");
         }
+        if token != position.token {
+            panic!("Invalid token");
+        }
+        if position.frozen {
+            panic!("Position frozen");
+        }
+        Self::rebase_position(&env, &mut position);
 
-        let token: Address = env.storage().instance().get(&DataKey::Token).unwrap();
-        let pool_address: Address = env.storage().instance().get(&DataKey::PoolAddress).unwrap();
-        let contract_address = env.current_contract_address();
+        // Step 1: Settle the position at the current bToken rate
+        let now = env.ledger().timestamp();
+        let mut withdrawal = Self::settle_withdrawal(&env, &position, &deposit_id, now);
 
-        // // Get current bToken rate from the pool
-        let pool_client = BlendPoolClient::new(&env, &pool_address);
-        let current_b_rate = pool_client.get_reserve(&token).data.b_rate;
-        
-        // Calculate bTokens and interest using Aave-like formula
-        let b_tokens = (position.amount * SCALAR_12) / position.b_rate;
-        let amount_to_withdraw = (b_tokens * current_b_rate) / SCALAR_12;
+        // Step 2: Redeem from Blend and pay out, or queue behind earlier withdrawals
+        // when Blend lacks the liquidity
+        let mut queue = Self::withdrawal_queue(&env, &token);
+        if queue.is_empty() {
+            if let Some(amount_out) = Self::complete_withdrawal(&env, &token, &mut withdrawal) {
+                if amount_out < min_amount_out {
+                    panic_with_error!(&env, VaquitaPoolError::InsufficientAmountOut);
+                }
+                return;
+            }
+        }
+        if withdrawal.payout < min_amount_out {
+            panic_with_error!(&env, VaquitaPoolError::InsufficientAmountOut);
+        }
+        position.frozen = true;
+        env.storage().instance().set(&DataKey::Positions(deposit_id.clone()), &position);
+        queue.push_back(withdrawal.clone());
+        env.storage().instance().set(&DataKey::WithdrawalQueue(token.clone()), &queue);
+        env.events().publish(
+            (Symbol::new(&env, "withdrawal_queued"), caller),
+            (deposit_id, withdrawal.payout),
+        );
+    }
+
+    /// Pays queued withdrawals in FIFO order, at most `n` of them, stopping at the
+    /// first one Blend cannot redeem yet. Open to anyone. Returns how many were paid.
+    pub fn process_withdrawal_queue(env: Env, token: Address, n: u32) -> u32 {
+        let mut queue = Self::withdrawal_queue(&env, &token);
+        let mut processed = 0;
+        while processed < n {
+            let Some(mut withdrawal) = queue.first() else {
+                break;
+            };
+            if Self::complete_withdrawal(&env, &token, &mut withdrawal).is_none() {
+                // Keep what was redeemed so far
+                queue.set(0, withdrawal);
+                break;
+            }
+            queue.pop_front();
+            processed += 1;
+        }
+        env.storage().instance().set(&DataKey::WithdrawalQueue(token.clone()), &queue);
+        processed
+    }
+
+    /// Takes the position out of its round and settles its rewards, fees and
+    /// forfeitures as of `now`. Payouts in other tokens are made right away; the
+    /// deposit token side is returned to be redeemed from Blend.
+    fn settle_withdrawal(env: &Env, position: &Position, deposit_id: &String, now: u64) -> QueuedWithdrawal {
+        let token = &position.token;
+        // Redeem the position's bTokens at the current rate
+        let amount_to_withdraw = Adapter::for_pool(env, token, &position.pool).value(env, position.b_tokens);
         let interest = if amount_to_withdraw - position.amount > 0 {
             amount_to_withdraw - position.amount
         } else {
             0
         };
-
-        // Step 1: Withdraw from Blend with the correct amount
-        let request = Request { 
-            request_type: 1u32, // Withdraw
-            address: token.clone(),
-            amount: amount_to_withdraw,
-        };
-        let requests = Vec::from_array(&env, [request]);
-        
-        // Use submit_with_allowance instead of submit for withdrawals
-        pool_client.submit(&contract_address, &contract_address, &contract_address, &requests);
-
-        let now = env.ledger().timestamp();
+        // Blend socialized a loss since the deposit, the position redeems its true share
+        let loss = (position.amount - amount_to_withdraw).max(0);
         let mut amount_to_transfer = amount_to_withdraw;
         let mut reward: i128 = 0;
 
+        // Harvest and vest streams first, both credit the period and its rounds
+        Self::harvest(env, token);
+        Self::accrue_streams(env, token, position.lock_period, now);
+
         // Get period data with proper error handling
         let mut period_data: Period = env.storage().instance()
-            .get(&DataKey::Periods(position.lock_period))
+            .get(&DataKey::Periods(token.clone(), position.lock_period))
             .unwrap_or_else(|| panic!("Period data not found for lock period: {}", position.lock_period));
+        let mut round = Self::load_round(env, token, position.lock_period, position.round);
+        Self::settle_round(env, &mut round, now);
+
+        // Rewards accrued while the position was deposited, then take it out of the round
+        let accrued_reward = Self::calculate_reward(env, &round, position);
+        let bonus_rewards = Self::accrue_bonus_rewards(env, position, &round, now);
+        let emissions = Self::calculate_emissions(env, position);
+        period_data.total_deposits -= position.amount;
+        round.total_deposits -= position.amount;
+        round.total_shares -= Self::shares(env, position);
+        let total_deposits: i128 = env.storage().instance().get(&DataKey::TotalDeposits(token.clone())).unwrap_or(0);
+        env.storage().instance().set(&DataKey::TotalDeposits(token.clone()), &(total_deposits - position.amount));
+        let user_deposits = Self::user_deposits(env, token, &position.owner);
+        env.storage().instance().set(&DataKey::UserDeposits(token.clone(), position.owner.clone()), &(user_deposits - position.amount));
 
+        let mut protocol_fee: i128 = 0;
+        let mut insured: i128 = 0;
+        let mut forfeit: i128 = 0;
         if now < position.finalization_time {
             // Early withdrawal fee on interest only
-            let early_fee: i128 = env.storage().instance().get(&DataKey::EarlyWithdrawalFee).unwrap();
-            let fee_amount = (interest * early_fee) / 10000;
-            let remaining_interest = interest - fee_amount;
-            let mut protocol_fees: i128 = env.storage().instance().get(&DataKey::ProtocolFees).unwrap();
-            protocol_fees += fee_amount;
-            env.storage().instance().set(&DataKey::ProtocolFees, &protocol_fees);
-            period_data.reward_pool += remaining_interest;
+            let early_fee: i128 = env.storage().instance().get(&DataKey::EarlyWithdrawalFee(token.clone())).unwrap();
+            let fee_amount = interest.fixed_mul_ceil(env, &early_fee, &10000);
+            let mut remaining_interest = interest - fee_amount;
+            // The insurance reserve takes its share of the fee and of the forfeited interest
+            let fee_share: i128 = env.storage().instance().get(&DataKey::InsuranceFeeShare(token.clone())).unwrap_or(0);
+            let forfeit_share: i128 = env.storage().instance().get(&DataKey::InsuranceForfeitShare(token.clone())).unwrap_or(0);
+            let mut insured_fee = fee_amount.fixed_mul_floor(env, &fee_share, &10000);
+            let mut insured_forfeit = remaining_interest.fixed_mul_floor(env, &forfeit_share, &10000);
+            insured = insured_fee + insured_forfeit;
+            if !Self::mints_b_tokens(env, token, insured) {
+                insured_fee = 0;
+                insured_forfeit = 0;
+                insured = 0;
+            }
+            remaining_interest -= insured_forfeit;
+            // The fee and forfeited interest only exist once the bTokens are redeemed
+            protocol_fee = fee_amount - insured_fee;
+            forfeit = remaining_interest;
+            // Accrued rewards go to the depositors that remain in the round
+            Self::distribute_rewards(env, &mut round, accrued_reward, now);
+            for (reward_token, mut index, bonus) in bonus_rewards.iter() {
+                Self::distribute_round_rewards(env, &mut index, &round, bonus, now);
+                env.storage().instance().set(
+                    &DataKey::RoundRewards(token.clone(), position.lock_period, position.round, reward_token),
+                    &index,
+                );
+            }
+            Self::distribute_emissions(env, token, emissions);
             amount_to_transfer -= interest;
         } else {
-            // Late withdrawal with additional rewards from reward pool
-            reward = Self::calculate_reward(&period_data, position.amount);
+            // Late withdrawal with additional rewards from the round's reward pool
+            reward = accrued_reward;
             period_data.reward_pool -= reward;
+            round.reward_pool -= reward;
             amount_to_transfer += reward; // Add reward pool rewards on top of interest
+            Self::pay_bonus_rewards(env, position, deposit_id, &bonus_rewards);
+            Self::pay_emissions(env, position, deposit_id, emissions);
         }
 
-        // Step 3: Transfer final amount from contract back to user
-        let token_client = TokenClient::new(&env, &token);
-        token_client.transfer(&contract_address, &caller, &amount_to_transfer);
+        env.storage().instance().set(&DataKey::Periods(token.clone(), position.lock_period), &period_data);
+        env.storage().instance().set(&DataKey::Rounds(token.clone(), position.lock_period, position.round), &round);
 
-        period_data.total_deposits -= position.amount;
-        env.storage().instance().set(&DataKey::Periods(position.lock_period), &period_data);
+        QueuedWithdrawal {
+            deposit_id: deposit_id.clone(),
+            owner: position.owner.clone(),
+            pool: position.pool.clone(),
+            lock_period: position.lock_period,
+            round: position.round,
+            b_tokens: position.b_tokens,
+            redeem_amount: amount_to_withdraw,
+            payout: amount_to_transfer,
+            protocol_fee,
+            insured,
+            forfeit,
+            loss,
+            reward,
+            redeemed: false,
+        }
+    }
+
+    /// Redeems a settled withdrawal from Blend, credits its fees and forfeitures and
+    /// pays it out. Returns the amount paid, or None when Blend cannot redeem it or the
+    /// insurance reserve cannot cover its loss yet. In the latter case the withdrawal is
+    /// marked redeemed and only waits for the insurance reserve.
+    fn complete_withdrawal(env: &Env, token: &Address, withdrawal: &mut QueuedWithdrawal) -> Option<i128> {
+        if !withdrawal.redeemed {
+            Self::redeem_withdrawal(env, token, withdrawal)?;
+        }
+
+        // Cover the loss from the insurance reserve as far as it goes
+        let mut amount_to_transfer = withdrawal.payout;
+        if withdrawal.loss > 0 {
+            let covered = Self::draw_insurance(env, token, withdrawal.loss)?;
+            amount_to_transfer += covered;
+            env.events().publish(
+                (Symbol::new(env, "loss_realized"), withdrawal.owner.clone()),
+                (withdrawal.deposit_id.clone(), withdrawal.loss, covered),
+            );
+        }
+
+        // Transfer final amount from contract back to user
+        Self::ensure_liquidity(env, token, amount_to_transfer);
+        let token_client = TokenClient::new(env, token);
+        token_client.transfer(&env.current_contract_address(), &withdrawal.owner, &amount_to_transfer);
 
         // Remove position
-        env.storage().instance().remove(&DataKey::Positions(deposit_id.clone()));
+        env.storage().instance().remove(&DataKey::Positions(withdrawal.deposit_id.clone()));
 
         // Emit event
         env.events().publish(
-            (Symbol::new(&env, "withdraw"), caller.clone()),
-            (deposit_id, token, amount_to_transfer, reward),
+            (Symbol::new(env, "withdraw"), withdrawal.owner.clone()),
+            (withdrawal.deposit_id.clone(), token.clone(), amount_to_transfer, withdrawal.reward),
         );
+        Some(amount_to_transfer)
     }
 
-    fn calculate_reward(period_data: &Period, amount: i128) -> i128 {
-        if period_data.total_deposits == 0 {
-            return 0;
+    /// Withdraws a settled withdrawal's bTokens from Blend to the contract and credits
+    /// the fees and forfeitures taken from them. Returns None, changing nothing, when
+    /// Blend cannot redeem them.
+    fn redeem_withdrawal(env: &Env, token: &Address, withdrawal: &mut QueuedWithdrawal) -> Option<()> {
+        // bTokens lost value since the settlement reduce the payout
+        let yield_source = Adapter::for_pool(env, token, &withdrawal.pool);
+        let redeem_amount = withdrawal.redeem_amount.min(yield_source.value(env, withdrawal.b_tokens));
+        let burned = yield_source.withdraw(env, redeem_amount)?;
+
+        // bTokens Blend did not burn to round the withdrawal stay with the protocol, or
+        // with the allocation pool's total for rebalancing
+        if let Some(pool) = &withdrawal.pool {
+            let pool_b_tokens: i128 = env.storage().instance().get(&DataKey::PoolBTokens(token.clone(), pool.clone())).unwrap_or(0);
+            env.storage().instance().set(&DataKey::PoolBTokens(token.clone(), pool.clone()), &(pool_b_tokens - burned));
+            let mut pool_positions = Self::pool_positions(env, token, pool);
+            if let Some(index) = pool_positions.first_index_of(&withdrawal.deposit_id) {
+                pool_positions.remove(index);
+            }
+            env.storage().instance().set(&DataKey::PoolPositions(token.clone(), pool.clone()), &pool_positions);
+        } else {
+            let total_b_tokens: i128 = env.storage().instance().get(&DataKey::TotalBTokens(token.clone())).unwrap_or(0);
+            env.storage().instance().set(&DataKey::TotalBTokens(token.clone()), &(total_b_tokens - withdrawal.b_tokens));
+            let protocol_b_tokens: i128 = env.storage().instance().get(&DataKey::ProtocolBTokens(token.clone())).unwrap_or(0);
+            env.storage().instance().set(&DataKey::ProtocolBTokens(token.clone()), &(protocol_b_tokens + withdrawal.b_tokens - burned));
+        }
+
+        Self::supply_to_insurance(env, token, withdrawal.insured);
+        if withdrawal.protocol_fee > 0 {
+            let protocol_fees: i128 = env.storage().instance().get(&DataKey::ProtocolFees(token.clone())).unwrap();
+            env.storage().instance().set(&DataKey::ProtocolFees(token.clone()), &(protocol_fees + withdrawal.protocol_fee));
+        }
+        // Forfeited interest goes to the depositors that remain in the round
+        if withdrawal.forfeit > 0 {
+            let now = env.ledger().timestamp();
+            let mut period_data = Self::load_period(env, token, withdrawal.lock_period);
+            period_data.reward_pool += withdrawal.forfeit;
+            env.storage().instance().set(&DataKey::Periods(token.clone(), withdrawal.lock_period), &period_data);
+            let mut round = Self::load_round(env, token, withdrawal.lock_period, withdrawal.round);
+            round.reward_pool += withdrawal.forfeit;
+            Self::distribute_rewards(env, &mut round, withdrawal.forfeit, now);
+            env.storage().instance().set(&DataKey::Rounds(token.clone(), withdrawal.lock_period, withdrawal.round), &round);
         }
-        (period_data.reward_pool * amount) / period_data.total_deposits
+
+        let shortfall = withdrawal.redeem_amount - redeem_amount;
+        withdrawal.loss += shortfall;
+        withdrawal.payout -= shortfall;
+        withdrawal.b_tokens = 0;
+        withdrawal.redeem_amount = 0;
+        withdrawal.redeemed = true;
+        Some(())
     }
 
-    // ---------- Owner functions ----------
-    pub fn withdraw_protocol_fees(env: Env, caller: Address) {
+    fn withdrawal_queue(env: &Env, token: &Address) -> Vec<QueuedWithdrawal> {
+        env.storage().instance().get(&DataKey::WithdrawalQueue(token.clone())).unwrap_or(Vec::new(env))
+    }
+
+    /// Moves positions from the lowest-paying allocation pool above its target weight
+    /// to the highest-paying one below it, within the limits of `RebalanceConfig`.
+    /// Open to anyone; the caller earns the keeper fee. Returns the amount moved.
+    pub fn rebalance(env: Env, caller: Address, token: Address) -> i128 {
         caller.require_auth();
-        Self::require_owner(&env, caller.clone());
-        let token: Address = env.storage().instance().get(&DataKey::Token).unwrap();
-        let contract_address = env.current_contract_address();
-        let protocol_fees: i128 = env.storage().instance().get(&DataKey::ProtocolFees).unwrap();
-        
-        if protocol_fees > 0 {
-            let token_client = TokenClient::new(&env, &token);
-            token_client.transfer(&contract_address, &caller, &protocol_fees);
-            env.storage().instance().set(&DataKey::ProtocolFees, &0i128);
+        let config: RebalanceConfig = env.storage().instance().get(&DataKey::RebalanceConfig(token.clone()))
+            .unwrap_or_else(|| panic!("Rebalancing disabled"));
+        let allocations = Self::pool_allocations(&env, &token);
+        let mut values = Vec::new(&env);
+        let mut total: i128 = 0;
+        let mut total_weight: i128 = 0;
+        for allocation in allocations.iter() {
+            let value = Self::pool_value(&env, &token, &allocation.pool);
+            values.push_back(value);
+            total += value;
+            total_weight += allocation.weight as i128;
+        }
+
+        // (pool, supply rate, amount it can give or take toward its target)
+        let mut source: Option<(Address, i128, i128)> = None;
+        let mut destination: Option<(Address, i128, i128)> = None;
+        for (allocation, value) in allocations.iter().zip(values.iter()) {
+            let target = total.fixed_mul_floor(&env, &(allocation.weight as i128), &total_weight);
+            let rate = Adapter::blend(&token, allocation.pool.clone()).supply_rate(&env);
+            if value > target {
+                if source.as_ref().is_none_or(|(_, source_rate, _)| rate < *source_rate) {
+                    source = Some((allocation.pool, rate, value - target));
+                }
+            } else if value < target && value < allocation.cap {
+                let room = (target - value).min(allocation.cap - value);
+                if destination.as_ref().is_none_or(|(_, destination_rate, _)| rate > *destination_rate) {
+                    destination = Some((allocation.pool, rate, room));
+                }
+            }
+        }
+        let (Some((from, from_rate, excess)), Some((to, to_rate, room))) = (source, destination) else {
+            panic!("Nothing to rebalance");
+        };
+        if to_rate - from_rate < config.min_rate_gap.max(1) {
+            panic!("Nothing to rebalance");
         }
+
+        // Whole positions move, oldest first, skipping those over the remaining limit.
+        // They are withdrawn and supplied together, at one rate per pool.
+        let limit = excess.min(room).min(config.max_amount);
+        let from_source = Adapter::blend(&token, from.clone());
+        let to_source = Adapter::blend(&token, to.clone());
+        let from_b_rate = from_source.rate(&env);
+        let to_b_rate = to_source.rate(&env);
+        let positions = Self::pool_positions(&env, &token, &from);
+        let inspected = positions.len().min(config.max_positions);
+        let mut moving = Vec::new(&env);
+        let mut kept = Vec::new(&env);
+        let mut moved: i128 = 0;
+        let mut b_tokens: i128 = 0;
+        for deposit_id in positions.slice(0..inspected).iter() {
+            let position: Position = env.storage().instance().get(&DataKey::Positions(deposit_id.clone())).unwrap();
+            let value = position.b_tokens.fixed_mul_floor(&env, &from_b_rate, &SCALAR_12);
+            let mints = value.fixed_div_floor(&env, &to_b_rate, &SCALAR_12) > 0;
+            if !position.frozen && mints && moved + value <= limit {
+                moved += value;
+                b_tokens += position.b_tokens;
+                moving.push_back(deposit_id);
+            } else {
+                kept.push_back(deposit_id);
+            }
+        }
+        kept.append(&positions.slice(inspected..positions.len()));
+        if moved <= 0 {
+            panic!("Nothing to rebalance");
+        }
+        let burned = from_source.withdraw(&env, moved).unwrap_or_else(|| panic!("Insufficient liquidity"));
+        let minted = to_source.supply(&env, moved);
+
+        // bTokens Blend did not burn stay in the old pool's total and rounding dust of
+        // the split in the new one's, as on withdrawal
+        let from_b_tokens: i128 = env.storage().instance().get(&DataKey::PoolBTokens(token.clone(), from.clone())).unwrap_or(0);
+        env.storage().instance().set(&DataKey::PoolBTokens(token.clone(), from.clone()), &(from_b_tokens - burned));
+        let to_b_tokens: i128 = env.storage().instance().get(&DataKey::PoolBTokens(token.clone(), to.clone())).unwrap_or(0);
+        env.storage().instance().set(&DataKey::PoolBTokens(token.clone(), to.clone()), &(to_b_tokens + minted));
+        let mut to_positions = Self::pool_positions(&env, &token, &to);
+        for deposit_id in moving.iter() {
+            let mut position: Position = env.storage().instance().get(&DataKey::Positions(deposit_id.clone())).unwrap();
+            let position_minted = position.b_tokens.fixed_mul_floor(&env, &minted, &b_tokens);
+            position.b_rate = position.b_rate.fixed_mul_floor(&env, &position.b_tokens, &position_minted);
+            position.b_tokens = position_minted;
+            position.pool = Some(to.clone());
+            env.storage().instance().set(&DataKey::Positions(deposit_id.clone()), &position);
+            to_positions.push_back(deposit_id);
+        }
+        env.storage().instance().set(&DataKey::PoolPositions(token.clone(), to.clone()), &to_positions);
+        env.storage().instance().set(&DataKey::PoolPositions(token.clone(), from.clone()), &kept);
+
+        let protocol_fees: i128 = env.storage().instance().get(&DataKey::ProtocolFees(token.clone())).unwrap();
+        let keeper_fee = moved.fixed_mul_floor(&env, &config.keeper_fee, &10000).min(protocol_fees);
+        if keeper_fee > 0 {
+            TokenClient::new(&env, &token).transfer(&env.current_contract_address(), &caller, &keeper_fee);
+            env.storage().instance().set(&DataKey::ProtocolFees(token.clone()), &(protocol_fees - keeper_fee));
+        }
+        env.events().publish(
+            (Symbol::new(&env, "rebalanced"), caller),
+            (from, to, moved, keeper_fee),
+        );
+        moved
     }
 
-    pub fn add_rewards(env: Env, caller: Address, period: u64, reward_amount: i128) {
+    /// Pays the rewards of a matured position in every reward token, leaving its
+    /// principal deposited.
+    pub fn claim_rewards(env: Env, caller: Address, deposit_id: String) {
         caller.require_auth();
-        Self::require_owner(&env, caller.clone());
-        
-        // First transfer the reward tokens from owner to contract
-        let token: Address = env.storage().instance().get(&DataKey::Token).unwrap();
-        let contract_address = env.current_contract_address();
-        let token_client = TokenClient::new(&env, &token);
-        token_client.transfer(&caller, &contract_address, &reward_amount);
-        
-        let supported: bool = env.storage().instance().get(&DataKey::SupportedLockPeriod(period)).unwrap_or(false);
-        if !supported {
-            panic!("Invalid period");
+
+        let mut position: Position = env.storage().instance().get(&DataKey::Positions(deposit_id.clone()))
+            .unwrap_or_else(|| panic!("Position not found"));
+        if caller != position.owner {
+            panic!("Not position owner");
         }
-        let mut period_data: Period = env.storage().instance().get(&DataKey::Periods(period)).unwrap_or(Period {
-            reward_pool: 0,
-            total_deposits: 0,
-        });
-        period_data.reward_pool += reward_amount;
-        env.storage().instance().set(&DataKey::Periods(period), &period_data);
+        if position.frozen {
+            panic!("Position frozen");
+        }
+        let now = env.ledger().timestamp();
+        if now < position.finalization_time {
+            panic!("Position not matured");
+        }
+
+        let token = position.token.clone();
+        Self::accrue_streams(&env, &token, position.lock_period, now);
+        let mut round = Self::load_round(&env, &token, position.lock_period, position.round);
+        Self::settle_round(&env, &mut round, now);
+        Self::harvest(&env, &token);
+        let reward = Self::calculate_reward(&env, &round, &position);
+        let bonus_rewards = Self::accrue_bonus_rewards(&env, &position, &round, now);
+        let emissions = Self::calculate_emissions(&env, &position);
+
+        if reward > 0 {
+            let mut period_data = Self::load_period(&env, &token, position.lock_period);
+            period_data.reward_pool -= reward;
+            round.reward_pool -= reward;
+            position.reward_debt += reward;
+            env.storage().instance().set(&DataKey::Periods(token.clone(), position.lock_period), &period_data);
+
+            Self::ensure_liquidity(&env, &token, reward);
+            let token_client = TokenClient::new(&env, &token);
+            token_client.transfer(&env.current_contract_address(), &caller, &reward);
+        }
+        env.storage().instance().set(&DataKey::Rounds(token.clone(), position.lock_period, position.round), &round);
+
+        Self::pay_bonus_rewards(&env, &position, &deposit_id, &bonus_rewards);
+        for (reward_token, _, bonus) in bonus_rewards.iter() {
+            let debt = position.reward_debts.get(reward_token.clone()).unwrap_or(0);
+            position.reward_debts.set(reward_token, debt + bonus);
+        }
+        Self::pay_emissions(&env, &position, &deposit_id, emissions);
+        position.emissions_debt += emissions;
+        env.storage().instance().set(&DataKey::Positions(deposit_id.clone()), &position);
+
+        env.events().publish(
+            (Symbol::new(&env, "claim_rewards"), caller),
+            (deposit_id, reward),
+        );
     }
 
-    pub fn update_early_withdrawal_fee(env: Env, caller: Address, new_fee: i128) {
-        Self::require_owner(&env, caller);
-        let basis_points: i128 = env.storage().instance().get(&DataKey::BasisPoints).unwrap();
-        if new_fee > basis_points {
-            panic!("Invalid fee");
+    /// Moves the rewards left in a round that started without (or lost all of) its
+    /// depositors into the next round of the same lock period.
+    pub fn rollover_rewards(env: Env, token: Address, period: u64, round_id: u32) {
+        let now = env.ledger().timestamp();
+        Self::accrue_streams(&env, &token, period, now);
+        let mut round: Round = env.storage().instance()
+            .get(&DataKey::Rounds(token.clone(), period, round_id))
+            .unwrap_or_else(|| panic!("Round not found"));
+        if now < round.start_time {
+            panic!("Round not started");
+        }
+        if round.total_deposits > 0 {
+            panic!("Round has deposits");
+        }
+        let amount = round.reward_pool;
+        round.reward_pool = 0;
+        round.undistributed_rewards = 0;
+        env.storage().instance().set(&DataKey::Rounds(token.clone(), period, round_id), &round);
+
+        let next_round_id = Self::next_round(&env, &token, period, now);
+        let mut next_round = Self::load_round(&env, &token, period, next_round_id);
+        next_round.reward_pool += amount;
+        Self::distribute_rewards(&env, &mut next_round, amount, now);
+        env.storage().instance().set(&DataKey::Rounds(token.clone(), period, next_round_id), &next_round);
+
+        for reward_token in Self::reward_tokens(&env).iter() {
+            let mut index = Self::load_round_rewards(&env, &token, period, round_id, &reward_token);
+            let bonus = index.reward_pool;
+            if bonus <= 0 {
+                continue;
+            }
+            index.reward_pool = 0;
+            index.undistributed_rewards = 0;
+            env.storage().instance().set(&DataKey::RoundRewards(token.clone(), period, round_id, reward_token.clone()), &index);
+
+            let mut next_index = Self::load_round_rewards(&env, &token, period, next_round_id, &reward_token);
+            next_index.reward_pool += bonus;
+            Self::distribute_round_rewards(&env, &mut next_index, &next_round, bonus, now);
+            env.storage().instance().set(&DataKey::RoundRewards(token.clone(), period, next_round_id, reward_token), &next_index);
         }
-        env.storage().instance().set(&DataKey::EarlyWithdrawalFee, &new_fee);
+
+        env.events().publish(
+            (Symbol::new(&env, "rollover_rewards"), token, period),
+            (round_id, next_round_id, amount),
+        );
     }
 
-    pub fn add_lock_period(env: Env, caller: Address, new_lock_period: u64) {
-        Self::require_owner(&env, caller);
-        let exists: bool = env.storage().instance().get(&DataKey::SupportedLockPeriod(new_lock_period)).unwrap_or(false);
-        if exists {
-            panic!("Lock period already supported");
+    // ---------- Emissions ----------
+    /// Claims the BLND the contract's Blend supply positions in `token` have earned and
+    /// sends it to the emissions destination, or spreads it pro rata over the token's
+    /// open positions.
+    pub fn harvest_emissions(env: Env, token: Address) -> i128 {
+        if !env.storage().instance().has(&DataKey::BlndToken) {
+            panic!("Emissions not configured");
         }
-        env.storage().instance().set(&DataKey::SupportedLockPeriod(new_lock_period), &true);
+        Self::harvest(&env, &token)
     }
 
-    // ---------- View functions ----------
-    pub fn get_position(env: Env, deposit_id: String) -> Option<Position> {
-        env.storage().instance().get(&DataKey::Positions(deposit_id))
+    fn harvest(env: &Env, token: &Address) -> i128 {
+        let blnd: Option<Address> = env.storage().instance().get(&DataKey::BlndToken);
+        let Some(blnd) = blnd else {
+            return 0;
+        };
+        let contract_address = env.current_contract_address();
+
+        // bTokens of reserve `i` accrue emissions under reserve token ID `i * 2 + 1`
+        let mut claimed: i128 = 0;
+        for pool_address in Self::blend_pools(env, token).iter() {
+            let pool_client = BlendPoolClient::new(env, &pool_address);
+            let reserve_index = pool_client.get_reserve(token).config.index;
+            let reserve_token_ids = Vec::from_array(env, [reserve_index * 2 + 1]);
+            claimed += pool_client.claim(&contract_address, &reserve_token_ids, &contract_address);
+        }
+        if claimed <= 0 {
+            return 0;
+        }
+
+        let destination: Option<Address> = env.storage().instance().get(&DataKey::EmissionsDestination);
+        let compounding: Option<CompoundingConfig> = env.storage().instance().get(&DataKey::CompoundingConfig(token.clone()));
+        match (destination, compounding) {
+            (Some(destination), _) => {
+                TokenClient::new(env, &blnd).transfer(&contract_address, &destination, &claimed);
+            }
+            (None, Some(compounding)) => {
+                // Harvesting runs inside deposits and withdrawals, which must not fail
+                // because Comet can't meet the minimum price, so the BLND is shared instead
+                if !Self::compound(env, token, &blnd, claimed, &compounding) {
+                    Self::distribute_emissions(env, token, claimed);
+                }
+            }
+            (None, None) => Self::distribute_emissions(env, token, claimed),
+        }
+
+        env.events().publish((Symbol::new(env, "harvest_emissions"), token.clone()), (blnd, claimed));
+        claimed
+    }
+
+    /// Blend pools the contract supplies `token` to: the configured pool when Blend is
+    /// the yield source, and every allocation pool.
+    fn blend_pools(env: &Env, token: &Address) -> Vec<Address> {
+        let mut pools = Vec::new(env);
+        if matches!(Adapter::load(env, token), Adapter::Blend(_)) {
+            pools.push_back(env.storage().instance().get::<_, Address>(&DataKey::PoolAddress(token.clone())).unwrap());
+        }
+        for allocation in Self::pool_allocations(env, token).iter() {
+            if !pools.contains(&allocation.pool) {
+                pools.push_back(allocation.pool);
+            }
+        }
+        pools
     }
 
-    pub fn get_period_data(env: Env, period: u64) -> Option<Period> {
-        env.storage().instance().get(&DataKey::Periods(period))
+    fn pool_allocations(env: &Env, token: &Address) -> Vec<PoolAllocation> {
+        env.storage().instance().get(&DataKey::PoolAllocations(token.clone())).unwrap_or(Vec::new(env))
     }
-}
\ No newline at end of file
+
+    /// Healthy allocation pool furthest below its target weight that can take `amount`
+    /// under its cap. None, the configured yield source, when no allocation is set.
+    fn route_deposit(env: &Env, token: &Address, amount: i128) -> Option<Address> {
+        let allocations = Self::pool_allocations(env, token);
+        if allocations.is_empty() {
+            return None;
+        }
+        let mut values = Vec::new(env);
+        let mut total = amount;
+        let mut total_weight: i128 = 0;
+        for allocation in allocations.iter() {
+            let value = Self::pool_value(env, token, &allocation.pool);
+            values.push_back(value);
+            total += value;
+            total_weight += allocation.weight as i128;
+        }
+
+        let mut best: Option<(Address, i128)> = None;
+        let mut unhealthy: Option<VaquitaPoolError> = None;
+        for (allocation, value) in allocations.iter().zip(values.iter()) {
+            if value + amount > allocation.cap {
+                continue;
+            }
+            let target = total.fixed_mul_floor(env, &(allocation.weight as i128), &total_weight);
+            let deficit = target - value;
+            if best.as_ref().is_some_and(|(_, best_deficit)| deficit <= *best_deficit) {
+                continue;
+            }
+            match Adapter::blend(token, allocation.pool.clone()).check_supply(env, amount) {
+                Ok(()) => best = Some((allocation.pool, deficit)),
+                Err(error) => unhealthy = Some(error),
+            }
+        }
+        match best {
+            Some((pool, _)) => Some(pool),
+            None => panic_with_error!(env, unhealthy.unwrap_or(VaquitaPoolError::AllocationCapReached)),
+        }
+    }
+
+    /// Deposit ids of the open positions supplied to the allocation pool `pool`, oldest first.
+    fn pool_positions(env: &Env, token: &Address, pool: &Address) -> Vec<String> {
+        env.storage().instance().get(&DataKey::PoolPositions(token.clone(), pool.clone())).unwrap_or(Vec::new(env))
+    }
+
+    /// Value in the deposit token of the positions supplied to the allocation pool `pool`.
+    fn pool_value(env: &Env, token: &Address, pool: &Address) -> i128 {
+        let b_tokens: i128 = env.storage().instance().get(&DataKey::PoolBTokens(token.clone(), pool.clone())).unwrap_or(0);
+        Adapter::blend(token, pool.clone()).value(env, b_tokens)
+    }
+
+    /// Swaps `amount` of BLND into the deposit token, supplies the proceeds to Blend
+    /// and credits them to the lock periods' reward pools. Returns false, keeping the
+    /// BLND, when Comet can't fill the swap at the minimum price.
+    fn compound(env: &Env, token: &Address, blnd: &Address, amount: i128, compounding: &CompoundingConfig) -> bool {
+        let contract_address = env.current_contract_address();
+
+        // Comet pulls the BLND by approving itself until the next 100k ledger boundary
+        // on the contract's behalf, which the contract has to authorize up front
+        let min_amount_out = amount.fixed_mul_ceil(env, &compounding.min_price, &SCALAR_7);
+        let expiration_ledger = (env.ledger().sequence() / 100_000 + 1) * 100_000;
+        env.authorize_as_current_contract(vec![
+            env,
+            InvokerContractAuthEntry::Contract(SubContractInvocation {
+                context: ContractContext {
+                    contract: blnd.clone(),
+                    fn_name: Symbol::new(env, "approve"),
+                    args: (
+                        contract_address.clone(),
+                        compounding.comet.clone(),
+                        amount,
+                        expiration_ledger,
+                    ).into_val(env),
+                },
+                sub_invocations: vec![env],
+            }),
+        ]);
+        let comet_client = CometClient::new(env, &compounding.comet);
+        let swap = comet_client.try_swap_exact_amount_in(
+            blnd,
+            &amount,
+            token,
+            &min_amount_out,
+            &i128::MAX,
+            &contract_address,
+        );
+        let Ok(Ok((amount_out, _))) = swap else {
+            return false;
+        };
+        if amount_out <= 0 {
+            return true;
+        }
+
+        // Keep the proceeds earning yield until they are paid out
+        let minted = Adapter::load(env, token).supply(env, amount_out);
+        let protocol_b_tokens: i128 = env.storage().instance().get(&DataKey::ProtocolBTokens(token.clone())).unwrap_or(0);
+        env.storage().instance().set(&DataKey::ProtocolBTokens(token.clone()), &(protocol_b_tokens + minted));
+
+        // Split between the lock periods by their deposits, rounding dust to the last one
+        let now = env.ledger().timestamp();
+        let total_deposits: i128 = env.storage().instance().get(&DataKey::TotalDeposits(token.clone())).unwrap_or(0);
+        let lock_periods = Self::lock_periods(env, token);
+        let mut remaining = amount_out;
+        for (i, period) in lock_periods.iter().enumerate() {
+            let share = if i as u32 + 1 == lock_periods.len() {
+                remaining
+            } else if total_deposits > 0 {
+                amount_out.fixed_mul_floor(env, &Self::load_period(env, token, period).total_deposits, &total_deposits)
+            } else {
+                0
+            };
+            remaining -= share;
+            Self::credit_period_rewards(env, token, period, share, now);
+        }
+
+        env.events().publish(
+            (Symbol::new(env, "compound_emissions"), token.clone()),
+            (amount, amount_out),
+        );
+        true
+    }
+
+    /// Credits `amount` of deposit token rewards to a lock period, split between the
+    /// round in progress and the round open for deposits by their deposits.
+    fn credit_period_rewards(env: &Env, token: &Address, period: u64, amount: i128, now: u64) {
+        if amount <= 0 {
+            return;
+        }
+        Self::accrue_streams(env, token, period, now);
+        let mut period_data = Self::load_period(env, token, period);
+        period_data.reward_pool += amount;
+        env.storage().instance().set(&DataKey::Periods(token.clone(), period), &period_data);
+
+        let next_round_id = Self::next_round(env, token, period, now);
+        let mut next_round = Self::load_round(env, token, period, next_round_id);
+        let mut next_share = amount;
+        if next_round_id > 0 {
+            let running_round_id = next_round_id - 1;
+            let mut running_round = Self::load_round(env, token, period, running_round_id);
+            Self::settle_round(env, &mut running_round, now);
+            let round_deposits = running_round.total_deposits + next_round.total_deposits;
+            let running_share = if round_deposits > 0 {
+                amount.fixed_mul_floor(env, &running_round.total_deposits, &round_deposits)
+            } else {
+                0
+            };
+            running_round.reward_pool += running_share;
+            Self::distribute_rewards(env, &mut running_round, running_share, now);
+            env.storage().instance().set(&DataKey::Rounds(token.clone(), period, running_round_id), &running_round);
+            next_share -= running_share;
+        }
+        next_round.reward_pool += next_share;
+        Self::distribute_rewards(env, &mut next_round, next_share, now);
+        env.storage().instance().set(&DataKey::Rounds(token.clone(), period, next_round_id), &next_round);
+    }
+
+    /// Current value in the deposit token of `b_tokens`.
+    fn b_tokens_value(env: &Env, token: &Address, b_tokens: i128) -> i128 {
+        if b_tokens <= 0 {
+            return 0;
+        }
+        Adapter::load(env, token).value(env, b_tokens)
+    }
+
+    /// Withdraws protocol-owned bTokens (compounded rewards and rounding dust) from
+    /// Blend when the contract's idle balance cannot cover a payment of `amount`.
+    fn ensure_liquidity(env: &Env, token: &Address, amount: i128) {
+        let balance = TokenClient::new(env, token).balance(&env.current_contract_address());
+        let protocol_b_tokens: i128 = env.storage().instance().get(&DataKey::ProtocolBTokens(token.clone())).unwrap_or(0);
+        if balance >= amount || protocol_b_tokens <= 0 {
+            return;
+        }
+
+        let available = Self::b_tokens_value(env, token, protocol_b_tokens);
+        let shortfall = (amount - balance).min(available);
+        if shortfall <= 0 {
+            return;
+        }
+        let Some(burned) = Adapter::load(env, token).withdraw(env, shortfall) else {
+            return;
+        };
+        env.storage().instance().set(&DataKey::ProtocolBTokens(token.clone()), &(protocol_b_tokens - burned).max(0));
+    }
+
+    /// Supplies `amount` held by the contract to Blend on behalf of the insurance
+    /// reserve. Returns false, leaving the tokens idle, when it is too small to mint
+    /// a bToken.
+    fn supply_to_insurance(env: &Env, token: &Address, amount: i128) -> bool {
+        if !Self::mints_b_tokens(env, token, amount) {
+            return false;
+        }
+        let minted = Adapter::load(env, token).supply(env, amount);
+        let insurance_b_tokens: i128 = env.storage().instance().get(&DataKey::InsuranceBTokens(token.clone())).unwrap_or(0);
+        env.storage().instance().set(&DataKey::InsuranceBTokens(token.clone()), &(insurance_b_tokens + minted));
+        true
+    }
+
+    /// Converts the position's bTokens through the pool migrations since it was minted,
+    /// and out of its allocation pool if that pool was emptied by an emergency exit.
+    fn rebase_position(env: &Env, position: &mut Position) {
+        let token = &position.token;
+        if let Some(pool) = &position.pool {
+            let exit: Option<PoolExit> = env.storage().instance().get(&DataKey::PoolExits(token.clone(), pool.clone()));
+            let Some(exit) = exit else {
+                return;
+            };
+            position.b_tokens = position.b_tokens.fixed_mul_floor(env, &exit.b_tokens_after, &exit.b_tokens_before);
+            position.b_rate = position.b_rate.fixed_mul_floor(env, &exit.b_tokens_before, &exit.b_tokens_after);
+            position.pool = None;
+            position.pool_epoch = exit.pool_epoch;
+        }
+        let epoch: u32 = env.storage().instance().get(&DataKey::PoolEpoch(token.clone())).unwrap_or(0);
+        while position.pool_epoch < epoch {
+            let migration: PoolMigration = env.storage().instance()
+                .get(&DataKey::PoolMigrations(token.clone(), position.pool_epoch))
+                .unwrap();
+            position.b_tokens = position.b_tokens.fixed_mul_floor(env, &migration.b_tokens_after, &migration.b_tokens_before);
+            position.b_rate = position.b_rate.fixed_mul_floor(env, &migration.b_tokens_before, &migration.b_tokens_after);
+            position.pool_epoch += 1;
+        }
+    }
+
+    /// Rebases the contract's accounting after its `b_tokens_before` were replaced by
+    /// `b_tokens_after` of another yield source, and records the migration positions
+    /// are rebased through. Rounding dust goes to the protocol.
+    fn rebase_pool(env: &Env, token: &Address, b_tokens_before: i128, b_tokens_after: i128) {
+        let rebase = |key: &DataKey| -> i128 {
+            let b_tokens: i128 = env.storage().instance().get(key).unwrap_or(0);
+            let rebased = b_tokens.fixed_mul_floor(env, &b_tokens_after, &b_tokens_before);
+            env.storage().instance().set(key, &rebased);
+            rebased
+        };
+        let accounted = rebase(&DataKey::TotalBTokens(token.clone())) + rebase(&DataKey::InsuranceBTokens(token.clone())) + rebase(&DataKey::ProtocolBTokens(token.clone()));
+        let protocol_b_tokens: i128 = env.storage().instance().get(&DataKey::ProtocolBTokens(token.clone())).unwrap();
+        env.storage().instance().set(&DataKey::ProtocolBTokens(token.clone()), &(protocol_b_tokens + b_tokens_after - accounted));
+
+        // Queued withdrawals are redeemed from the new source
+        let mut queue = Self::withdrawal_queue(env, token);
+        for i in 0..queue.len() {
+            let mut withdrawal = queue.get(i).unwrap();
+            if withdrawal.pool.is_none() {
+                withdrawal.b_tokens = withdrawal.b_tokens.fixed_mul_floor(env, &b_tokens_after, &b_tokens_before);
+                queue.set(i, withdrawal);
+            }
+        }
+        env.storage().instance().set(&DataKey::WithdrawalQueue(token.clone()), &queue);
+
+        let epoch: u32 = env.storage().instance().get(&DataKey::PoolEpoch(token.clone())).unwrap_or(0);
+        env.storage().instance().set(
+            &DataKey::PoolMigrations(token.clone(), epoch),
+            &PoolMigration { b_tokens_before, b_tokens_after },
+        );
+        env.storage().instance().set(&DataKey::PoolEpoch(token.clone()), &(epoch + 1));
+    }
+
+    /// Whether supplying `amount` to Blend is large enough to mint a bToken.
+    fn mints_b_tokens(env: &Env, token: &Address, amount: i128) -> bool {
+        amount > 0 && amount.fixed_div_floor(env, &Adapter::load(env, token).rate(env), &SCALAR_12) > 0
+    }
+
+    /// Withdraws up to `loss` from the insurance reserve to the contract and returns
+    /// the amount covered, or None when Blend cannot redeem it.
+    fn draw_insurance(env: &Env, token: &Address, loss: i128) -> Option<i128> {
+        let insurance_b_tokens: i128 = env.storage().instance().get(&DataKey::InsuranceBTokens(token.clone())).unwrap_or(0);
+        let covered = loss.min(Self::b_tokens_value(env, token, insurance_b_tokens));
+        if covered <= 0 {
+            return Some(0);
+        }
+        let burned = Adapter::load(env, token).withdraw(env, covered)?;
+        env.storage().instance().set(&DataKey::InsuranceBTokens(token.clone()), &(insurance_b_tokens - burned));
+        Some(covered)
+    }
+
+    /// Spreads `amount` of BLND over the open positions, or keeps it for the next
+    /// distribution when there are none.
+    fn distribute_emissions(env: &Env, token: &Address, amount: i128) {
+        if amount <= 0 {
+            return;
+        }
+        let undistributed: i128 = env.storage().instance().get(&DataKey::UndistributedEmissions(token.clone())).unwrap_or(0);
+        let total_deposits: i128 = env.storage().instance().get(&DataKey::TotalDeposits(token.clone())).unwrap_or(0);
+        if total_deposits == 0 {
+            env.storage().instance().set(&DataKey::UndistributedEmissions(token.clone()), &(undistributed + amount));
+            return;
+        }
+        let emissions_per_share: i128 = env.storage().instance().get(&DataKey::EmissionsPerShare(token.clone())).unwrap_or(0);
+        let increase = (amount + undistributed).fixed_div_floor(env, &total_deposits, &SCALAR_12);
+        env.storage().instance().set(&DataKey::EmissionsPerShare(token.clone()), &(emissions_per_share + increase));
+        env.storage().instance().set(&DataKey::UndistributedEmissions(token.clone()), &0i128);
+    }
+
+    /// BLND emissions a position has accrued since it was deposited.
+    fn calculate_emissions(env: &Env, position: &Position) -> i128 {
+        let token = &position.token;
+        let emissions_per_share: i128 = env.storage().instance().get(&DataKey::EmissionsPerShare(token.clone())).unwrap_or(0);
+        (position.amount.fixed_mul_floor(env, &emissions_per_share, &SCALAR_12) - position.emissions_debt).max(0)
+    }
+
+    fn pay_emissions(env: &Env, position: &Position, deposit_id: &String, emissions: i128) {
+        if emissions <= 0 {
+            return;
+        }
+        let blnd: Address = env.storage().instance().get(&DataKey::BlndToken).unwrap();
+        TokenClient::new(env, &blnd).transfer(&env.current_contract_address(), &position.owner, &emissions);
+        env.events().publish(
+            (Symbol::new(env, "reward_paid"), position.owner.clone()),
+            (deposit_id.clone(), blnd, emissions),
+        );
+    }
+
+    fn lock_periods(env: &Env, token: &Address) -> Vec<u64> {
+        env.storage().instance().get(&DataKey::LockPeriods(token.clone())).unwrap_or(Vec::new(env))
+    }
+
+    fn default_lock_period(env: &Env) -> LockPeriodConfig {
+        LockPeriodConfig {
+            name: String::from_str(env, ""),
+            min_deposit: 1,
+            max_deposit: i128::MAX,
+            cap: i128::MAX,
+            bonus_multiplier: 10000,
+            status: LockPeriodStatus::Active,
+        }
+    }
+
+    fn load_lock_period(env: &Env, token: &Address, period: u64) -> LockPeriodConfig {
+        env.storage().instance()
+            .get(&DataKey::LockPeriodConfigs(token.clone(), period))
+            .unwrap_or_else(|| panic!("Invalid period"))
+    }
+
+    fn deposit_caps(env: &Env, token: &Address) -> DepositCaps {
+        env.storage().instance()
+            .get(&DataKey::DepositCaps(token.clone()))
+            .unwrap_or(DepositCaps { tvl_cap: i128::MAX, user_cap: i128::MAX })
+    }
+
+    fn user_deposits(env: &Env, token: &Address, user: &Address) -> i128 {
+        env.storage().instance().get(&DataKey::UserDeposits(token.clone(), user.clone())).unwrap_or(0)
+    }
+
+    fn deposit_capacity(env: &Env, token: &Address, period: u64, user: &Address) -> DepositCapacity {
+        let caps = Self::deposit_caps(env, token);
+        let total_deposits: i128 = env.storage().instance().get(&DataKey::TotalDeposits(token.clone())).unwrap_or(0);
+        let period_deposits = Self::load_period(env, token, period).total_deposits;
+        DepositCapacity {
+            period: (Self::load_lock_period(env, token, period).cap - period_deposits).max(0),
+            tvl: (caps.tvl_cap - total_deposits).max(0),
+            user: (caps.user_cap - Self::user_deposits(env, token, user)).max(0),
+        }
+    }
+
+    /// Terms of `period`, which must still accept deposits.
+    fn active_lock_period(env: &Env, token: &Address, period: u64) -> LockPeriodConfig {
+        let lock_period = Self::load_lock_period(env, token, period);
+        if lock_period.status == LockPeriodStatus::Disabled {
+            panic!("Lock period disabled");
+        }
+        lock_period
+    }
+
+    /// Rejects funding rewards for a disabled `period`, whose rounds nobody can join.
+    fn require_fundable_period(env: &Env, token: &Address, period: u64) {
+        Self::active_lock_period(env, token, period);
+    }
+
+    fn load_period(env: &Env, token: &Address, period: u64) -> Period {
+        env.storage().instance().get(&DataKey::Periods(token.clone(), period)).unwrap_or(Period {
+            reward_pool: 0,
+            total_deposits: 0,
+        })
+    }
+
+    fn schedule_rounds(env: &Env, token: &Address, period: u64) {
+        Self::check_round_schedule(env, period, period);
+        let schedule = RoundSchedule {
+            first_start: env.ledger().timestamp() + period,
+            open_window: period,
+        };
+        env.storage().instance().set(&DataKey::RoundSchedules(token.clone(), period), &schedule);
+    }
+
+    /// Rounds last `period` and open for deposits `open_window` before they start,
+    /// which must not reach back past the start of the previous round.
+    fn check_round_schedule(env: &Env, period: u64, open_window: u64) {
+        if period == 0 {
+            panic_with_error!(env, VaquitaPoolError::InvalidPeriod);
+        }
+        if open_window == 0 || open_window > period {
+            panic_with_error!(env, VaquitaPoolError::InvalidOpenWindow);
+        }
+    }
+
+    fn load_round_schedule(env: &Env, token: &Address, period: u64) -> RoundSchedule {
+        env.storage().instance()
+            .get(&DataKey::RoundSchedules(token.clone(), period))
+            .unwrap_or_else(|| panic!("Invalid period"))
+    }
+
+    /// The first round of `period` that has not started yet at `now`.
+    fn next_round(env: &Env, token: &Address, period: u64, now: u64) -> u32 {
+        let schedule = Self::load_round_schedule(env, token, period);
+        if now < schedule.first_start {
+            return 0;
+        }
+        ((now - schedule.first_start) / period + 1) as u32
+    }
+
+    fn load_round(env: &Env, token: &Address, period: u64, round_id: u32) -> Round {
+        env.storage().instance().get(&DataKey::Rounds(token.clone(), period, round_id)).unwrap_or_else(|| {
+            let schedule = Self::load_round_schedule(env, token, period);
+            let start_time = schedule.first_start + round_id as u64 * period;
+            Round {
+                start_time,
+                end_time: start_time + period,
+                reward_pool: 0,
+                total_deposits: 0,
+                total_shares: 0,
+                reward_per_share: 0,
+                undistributed_rewards: 0,
+            }
+        })
+    }
+
+    /// Splits the round's pot between its depositors once it has started.
+    fn settle_round(env: &Env, round: &mut Round, now: u64) {
+        if now < round.start_time || round.undistributed_rewards == 0 || round.total_shares == 0 {
+            return;
+        }
+        let undistributed = round.undistributed_rewards;
+        round.undistributed_rewards = 0;
+        Self::distribute_rewards(env, round, undistributed, now);
+    }
+
+    fn reward_tokens(env: &Env) -> Vec<Address> {
+        env.storage().instance().get(&DataKey::RewardTokens).unwrap_or(Vec::new(env))
+    }
+
+    fn load_round_rewards(env: &Env, token: &Address, period: u64, round_id: u32, reward_token: &Address) -> RewardIndex {
+        env.storage().instance()
+            .get(&DataKey::RoundRewards(token.clone(), period, round_id, reward_token.clone()))
+            .unwrap_or(RewardIndex {
+                reward_pool: 0,
+                reward_per_share: 0,
+                undistributed_rewards: 0,
+            })
+    }
+
+    /// Bonus token counterpart of `settle_round`.
+    fn settle_round_rewards(env: &Env, index: &mut RewardIndex, round: &Round, now: u64) {
+        if now < round.start_time || index.undistributed_rewards == 0 || round.total_shares == 0 {
+            return;
+        }
+        let undistributed = index.undistributed_rewards;
+        index.undistributed_rewards = 0;
+        Self::distribute_round_rewards(env, index, round, undistributed, now);
+    }
+
+    /// Bonus token counterpart of `distribute_rewards`.
+    fn distribute_round_rewards(env: &Env, index: &mut RewardIndex, round: &Round, amount: i128, now: u64) {
+        if amount <= 0 {
+            return;
+        }
+        if now < round.start_time || round.total_shares == 0 {
+            index.undistributed_rewards += amount;
+            return;
+        }
+        index.reward_per_share += amount.fixed_div_floor(env, &round.total_shares, &SCALAR_12);
+    }
+
+    /// Settles the round's bonus token indexes and returns, per reward token, the
+    /// index and the amount the position accrued in it.
+    fn accrue_bonus_rewards(env: &Env, position: &Position, round: &Round, now: u64) -> Vec<(Address, RewardIndex, i128)> {
+        let mut accrued = Vec::new(env);
+        for reward_token in Self::reward_tokens(env).iter() {
+            let mut index = Self::load_round_rewards(env, &position.token, position.lock_period, position.round, &reward_token);
+            Self::settle_round_rewards(env, &mut index, round, now);
+            let debt = position.reward_debts.get(reward_token.clone()).unwrap_or(0);
+            let reward = (Self::shares(env, position).fixed_mul_floor(env, &index.reward_per_share, &SCALAR_12) - debt).max(0);
+            accrued.push_back((reward_token, index, reward));
+        }
+        accrued
+    }
+
+    fn pay_bonus_rewards(env: &Env, position: &Position, deposit_id: &String, bonus_rewards: &Vec<(Address, RewardIndex, i128)>) {
+        let token = &position.token;
+        let contract_address = env.current_contract_address();
+        for (reward_token, mut index, bonus) in bonus_rewards.iter() {
+            index.reward_pool -= bonus;
+            env.storage().instance().set(
+                &DataKey::RoundRewards(token.clone(), position.lock_period, position.round, reward_token.clone()),
+                &index,
+            );
+            if bonus > 0 {
+                TokenClient::new(env, &reward_token).transfer(&contract_address, &position.owner, &bonus);
+                env.events().publish(
+                    (Symbol::new(env, "reward_paid"), position.owner.clone()),
+                    (deposit_id.clone(), reward_token, bonus),
+                );
+            }
+        }
+    }
+
+    /// Amount of `stream` released by `time`.
+    fn stream_released_at(env: &Env, stream: &RewardStream, time: u64) -> i128 {
+        if time <= stream.start_time {
+            return 0;
+        }
+        if time >= stream.end_time {
+            return stream.amount;
+        }
+        let elapsed = (time - stream.start_time) as i128;
+        let duration = (stream.end_time - stream.start_time) as i128;
+        stream.amount.fixed_mul_floor(env, &elapsed, &duration)
+    }
+
+    /// Credits what the period's reward streams released since the last accrual to
+    /// the rounds that were running while it vested.
+    fn accrue_streams(env: &Env, token: &Address, period: u64, now: u64) {
+        let streams: Vec<RewardStream> = env.storage().instance()
+            .get(&DataKey::RewardStreams(token.clone(), period))
+            .unwrap_or(Vec::new(env));
+        if streams.is_empty() {
+            return;
+        }
+
+        let mut total_released: i128 = 0;
+        for (round_id, from, released) in Self::stream_releases(env, token, period, &streams, now).iter() {
+            let mut round = Self::load_round(env, token, period, round_id);
+            round.reward_pool += released;
+            Self::distribute_rewards(env, &mut round, released, from);
+            env.storage().instance().set(&DataKey::Rounds(token.clone(), period, round_id), &round);
+            total_released += released;
+        }
+
+        if total_released > 0 {
+            let mut period_data = Self::load_period(env, token, period);
+            period_data.reward_pool += total_released;
+            env.storage().instance().set(&DataKey::Periods(token.clone(), period), &period_data);
+        }
+
+        // Drop the streams that are fully released
+        let mut active = Vec::new(env);
+        for stream in streams.iter() {
+            if stream.end_time > now {
+                active.push_back(stream);
+            }
+        }
+        env.storage().instance().set(&DataKey::RewardStreams(token.clone(), period), &active);
+        env.storage().instance().set(&DataKey::StreamsAccruedAt(token.clone(), period), &now);
+    }
+
+    /// What `streams` release between the last accrual and `now`, as the round it is
+    /// credited to, the time it starts vesting in that round and the amount.
+    fn stream_releases(env: &Env, token: &Address, period: u64, streams: &Vec<RewardStream>, now: u64) -> Vec<(u32, u64, i128)> {
+        let mut releases = Vec::new(env);
+        let accrued_at: u64 = env.storage().instance()
+            .get(&DataKey::StreamsAccruedAt(token.clone(), period))
+            .unwrap_or(now);
+        if streams.is_empty() || now <= accrued_at {
+            return releases;
+        }
+
+        // Nothing vests before the earliest stream starts
+        let mut from = accrued_at;
+        let earliest_start = streams.iter().map(|stream| stream.start_time).min().unwrap();
+        if from < earliest_start {
+            from = earliest_start.min(now);
+        }
+
+        let schedule = Self::load_round_schedule(env, token, period);
+        while from < now {
+            let (round_id, segment_end) = if from < schedule.first_start {
+                (0, schedule.first_start.min(now))
+            } else {
+                let round_id = (from - schedule.first_start) / period;
+                (round_id as u32, (schedule.first_start + (round_id + 1) * period).min(now))
+            };
+            let mut released: i128 = 0;
+            for stream in streams.iter() {
+                released += Self::stream_released_at(env, &stream, segment_end) - Self::stream_released_at(env, &stream, from);
+            }
+            if released > 0 {
+                releases.push_back((round_id, from, released));
+            }
+            from = segment_end;
+        }
+        releases
+    }
+
+    /// Rewards a position has accrued since it was deposited.
+    fn calculate_reward(env: &Env, round: &Round, position: &Position) -> i128 {
+        (Self::shares(env, position).fixed_mul_floor(env, &round.reward_per_share, &SCALAR_12) - position.reward_debt).max(0)
+    }
+
+    /// Weight of the position in its round's rewards: its amount scaled by its multiplier.
+    fn shares(env: &Env, position: &Position) -> i128 {
+        position.amount.fixed_mul_floor(env, &(position.multiplier as i128), &10000)
+    }
+
+    /// Multiplier, in basis points, of a deposit by `user` into `lock_period`: the
+    /// period's bonus, the user's own multiplier and the partner boost if it applies.
+    fn reward_multiplier(env: &Env, user: &Address, lock_period: &LockPeriodConfig) -> u32 {
+        let mut multiplier = lock_period.bonus_multiplier as i128;
+        let user_multiplier: u32 = env.storage().instance().get(&DataKey::UserMultiplier(user.clone())).unwrap_or(10000);
+        multiplier = multiplier.fixed_mul_floor(env, &(user_multiplier as i128), &10000);
+        let boost: Option<PartnerBoost> = env.storage().instance().get(&DataKey::PartnerBoost);
+        if let Some(boost) = boost {
+            if TokenClient::new(env, &boost.token).balance(user) >= boost.min_balance {
+                multiplier = multiplier.fixed_mul_floor(env, &(boost.multiplier as i128), &10000);
+            }
+        }
+        multiplier.clamp(1, MAX_MULTIPLIER as i128) as u32
+    }
+
+    /// Credits `amount` to the round. Before the start it joins the pot split at the
+    /// start, afterwards it is spread over the deposits still in the round. The tokens
+    /// must already be accounted for in `reward_pool`.
+    fn distribute_rewards(env: &Env, round: &mut Round, amount: i128, now: u64) {
+        if amount <= 0 {
+            return;
+        }
+        if now < round.start_time || round.total_shares == 0 {
+            round.undistributed_rewards += amount;
+            return;
+        }
+        round.reward_per_share += amount.fixed_div_floor(env, &round.total_shares, &SCALAR_12);
+    }
+
+    // ---------- Owner functions ----------
+    /// Replaces the contract's code with the uploaded wasm `new_wasm_hash`, keeping its storage.
+    pub fn upgrade(env: Env, caller: Address, new_wasm_hash: BytesN<32>) {
+        caller.require_auth();
+        Self::require_owner(&env, caller);
+        env.deployer().update_current_contract_wasm(new_wasm_hash);
+    }
+
+    pub fn withdraw_protocol_fees(env: Env, caller: Address, token: Address) {
+        caller.require_auth();
+        Self::require_owner(&env, caller.clone());
+        let contract_address = env.current_contract_address();
+        let protocol_fees: i128 = env.storage().instance().get(&DataKey::ProtocolFees(token.clone())).unwrap();
+        
+        if protocol_fees > 0 {
+            let token_client = TokenClient::new(&env, &token);
+            token_client.transfer(&contract_address, &caller, &protocol_fees);
+            env.storage().instance().set(&DataKey::ProtocolFees(token.clone()), &0i128);
+        }
+    }
+
+    /// Moves accumulated protocol fees into the insurance reserve that covers Blend losses.
+    pub fn fund_insurance_buffer(env: Env, caller: Address, token: Address, amount: i128) {
+        caller.require_auth();
+        Self::require_owner(&env, caller);
+        let protocol_fees: i128 = env.storage().instance().get(&DataKey::ProtocolFees(token.clone())).unwrap();
+        if amount <= 0 || amount > protocol_fees {
+            panic!("Invalid amount");
+        }
+        if !Self::supply_to_insurance(&env, &token, amount) {
+            panic!("Invalid amount");
+        }
+        env.storage().instance().set(&DataKey::ProtocolFees(token.clone()), &(protocol_fees - amount));
+    }
+
+    /// Tops up the insurance reserve with `amount` of the deposit token. Open to anyone.
+    pub fn top_up_insurance(env: Env, caller: Address, token: Address, amount: i128) {
+        caller.require_auth();
+        TokenClient::new(&env, &token).transfer(&caller, &env.current_contract_address(), &amount);
+        if !Self::supply_to_insurance(&env, &token, amount) {
+            panic!("Invalid amount");
+        }
+        env.events().publish(
+            (Symbol::new(&env, "insurance_top_up"), caller),
+            (token, amount),
+        );
+    }
+
+    pub fn add_rewards(env: Env, caller: Address, token: Address, period: u64, reward_amount: i128) {
+        caller.require_auth();
+        Self::require_owner(&env, caller.clone());
+        
+        // First transfer the reward tokens from owner to contract
+        let contract_address = env.current_contract_address();
+        let token_client = TokenClient::new(&env, &token);
+        token_client.transfer(&caller, &contract_address, &reward_amount);
+        
+        Self::require_fundable_period(&env, &token, period);
+        let mut period_data = Self::load_period(&env, &token, period);
+        period_data.reward_pool += reward_amount;
+        env.storage().instance().set(&DataKey::Periods(token.clone(), period), &period_data);
+
+        // Rewards go to the pot of the next round, split by everyone who completes it
+        let now = env.ledger().timestamp();
+        let round_id = Self::next_round(&env, &token, period, now);
+        let mut round = Self::load_round(&env, &token, period, round_id);
+        round.reward_pool += reward_amount;
+        Self::distribute_rewards(&env, &mut round, reward_amount, now);
+        env.storage().instance().set(&DataKey::Rounds(token.clone(), period, round_id), &round);
+    }
+
+    /// Funds bonus rewards in a registered reward token. Like `add_rewards` they go
+    /// to the pot of the next round. Anyone can sponsor a round this way.
+    pub fn add_rewards_in(env: Env, caller: Address, token: Address, reward_token: Address, period: u64, reward_amount: i128) {
+        caller.require_auth();
+
+        if !Self::reward_tokens(&env).contains(&reward_token) {
+            panic!("Invalid reward token");
+        }
+        Self::require_fundable_period(&env, &token, period);
+        if reward_amount <= 0 {
+            panic!("Invalid amount");
+        }
+
+        let token_client = TokenClient::new(&env, &reward_token);
+        token_client.transfer(&caller, &env.current_contract_address(), &reward_amount);
+
+        let now = env.ledger().timestamp();
+        let round_id = Self::next_round(&env, &token, period, now);
+        let round = Self::load_round(&env, &token, period, round_id);
+        let mut index = Self::load_round_rewards(&env, &token, period, round_id, &reward_token);
+        index.reward_pool += reward_amount;
+        Self::distribute_round_rewards(&env, &mut index, &round, reward_amount, now);
+        env.storage().instance().set(&DataKey::RoundRewards(token.clone(), period, round_id, reward_token.clone()), &index);
+
+        env.events().publish(
+            (Symbol::new(&env, "add_rewards_in"), caller),
+            (token, reward_token, period, round_id, reward_amount),
+        );
+    }
+
+    /// Enables BLND emission harvesting. Without a `destination` the harvested BLND is
+    /// shared between the depositors.
+    pub fn update_emissions_config(env: Env, caller: Address, blnd_token: Address, destination: Option<Address>) {
+        caller.require_auth();
+        Self::require_owner(&env, caller);
+        // Settle what was earned under the previous configuration first
+        for token in Self::tokens(&env).iter() {
+            Self::harvest(&env, &token);
+        }
+        env.storage().instance().set(&DataKey::BlndToken, &blnd_token);
+        match destination {
+            Some(destination) => env.storage().instance().set(&DataKey::EmissionsDestination, &destination),
+            None => env.storage().instance().remove(&DataKey::EmissionsDestination),
+        }
+    }
+
+    /// Compounds harvested BLND into the deposit token through `comet`, or stops
+    /// compounding when `comet` is `None`.
+    pub fn update_compounding_config(env: Env, caller: Address, token: Address, comet: Option<Address>, min_price: i128) {
+        caller.require_auth();
+        Self::require_owner(&env, caller);
+        match comet {
+            Some(comet) => {
+                if min_price <= 0 {
+                    panic!("Invalid min price");
+                }
+                env.storage().instance().set(&DataKey::CompoundingConfig(token.clone()), &CompoundingConfig { comet, min_price });
+            }
+            None => env.storage().instance().remove(&DataKey::CompoundingConfig(token.clone())),
+        }
+    }
+
+    pub fn add_reward_token(env: Env, caller: Address, reward_token: Address) {
+        caller.require_auth();
+        Self::require_owner(&env, caller);
+        let mut reward_tokens = Self::reward_tokens(&env);
+        if Self::tokens(&env).contains(&reward_token) || reward_tokens.contains(&reward_token) {
+            panic!("Reward token already supported");
+        }
+        reward_tokens.push_back(reward_token);
+        env.storage().instance().set(&DataKey::RewardTokens, &reward_tokens);
+    }
+
+    /// Funds `amount` of rewards for `period` that are released linearly between
+    /// `start` and `end` to the depositors of the rounds running in that window.
+    pub fn add_rewards_stream(env: Env, caller: Address, token: Address, period: u64, amount: i128, start: u64, end: u64) {
+        caller.require_auth();
+        Self::require_owner(&env, caller.clone());
+
+        Self::require_fundable_period(&env, &token, period);
+        if amount <= 0 {
+            panic!("Invalid amount");
+        }
+        let now = env.ledger().timestamp();
+        if start < now || end <= start {
+            panic!("Invalid stream window");
+        }
+
+        let contract_address = env.current_contract_address();
+        let token_client = TokenClient::new(&env, &token);
+        token_client.transfer(&caller, &contract_address, &amount);
+
+        Self::accrue_streams(&env, &token, period, now);
+        let mut streams: Vec<RewardStream> = env.storage().instance()
+            .get(&DataKey::RewardStreams(token.clone(), period))
+            .unwrap_or(Vec::new(&env));
+        streams.push_back(RewardStream {
+            amount,
+            start_time: start,
+            end_time: end,
+        });
+        env.storage().instance().set(&DataKey::RewardStreams(token.clone(), period), &streams);
+        env.storage().instance().set(&DataKey::StreamsAccruedAt(token.clone(), period), &now);
+
+        env.events().publish(
+            (Symbol::new(&env, "add_rewards_stream"), token, period),
+            (amount, start, end),
+        );
+    }
+
+    pub fn update_early_withdrawal_fee(env: Env, caller: Address, token: Address, new_fee: i128) {
+        caller.require_auth();
+        Self::require_owner(&env, caller);
+        let basis_points: i128 = env.storage().instance().get(&DataKey::BasisPoints).unwrap();
+        if new_fee > basis_points {
+            panic!("Invalid fee");
+        }
+        env.storage().instance().set(&DataKey::EarlyWithdrawalFee(token.clone()), &new_fee);
+    }
+
+    /// Splits new deposits across `allocations`, each deposit going to the pool
+    /// furthest below its target weight. An empty list supplies to the configured
+    /// yield source again. Pools still holding deposits cannot be dropped.
+    pub fn set_pool_allocations(env: Env, caller: Address, token: Address, allocations: Vec<PoolAllocation>) {
+        caller.require_auth();
+        Self::require_owner(&env, caller);
+        let mut pools = Vec::new(&env);
+        for allocation in allocations.iter() {
+            if allocation.weight == 0 || allocation.cap <= 0 || pools.contains(&allocation.pool) {
+                panic!("Invalid allocation");
+            }
+            if env.storage().instance().has(&DataKey::PoolExits(token.clone(), allocation.pool.clone())) {
+                panic!("Pool exited");
+            }
+            pools.push_back(allocation.pool);
+        }
+        for allocation in Self::pool_allocations(&env, &token).iter() {
+            if !Self::pool_positions(&env, &token, &allocation.pool).is_empty() && !pools.contains(&allocation.pool) {
+                panic!("Pool in use");
+            }
+        }
+        env.storage().instance().set(&DataKey::PoolAllocations(token.clone()), &allocations);
+        env.storage().instance().remove(&DataKey::DepositsHalted(token.clone()));
+    }
+
+    /// Enables `rebalance` for `token` within the given limits, in `token` units.
+    pub fn update_rebalance_config(
+        env: Env,
+        caller: Address,
+        token: Address,
+        max_amount: i128,
+        max_positions: u32,
+        min_rate_gap: i128,
+        keeper_fee: i128,
+    ) {
+        caller.require_auth();
+        Self::require_owner(&env, caller);
+        if max_amount <= 0 || max_positions == 0 || min_rate_gap < 0 || !(0..=10000).contains(&keeper_fee) {
+            panic!("Invalid rebalance config");
+        }
+        env.storage().instance().set(
+            &DataKey::RebalanceConfig(token),
+            &RebalanceConfig { max_amount, max_positions, min_rate_gap, keeper_fee },
+        );
+    }
+
+    /// Sets the reward multiplier, in basis points, of `user`'s future deposits, e.g.
+    /// for a loyalty tier or an early cohort.
+    pub fn update_user_multiplier(env: Env, caller: Address, user: Address, multiplier: u32) {
+        caller.require_auth();
+        Self::require_owner(&env, caller);
+        if !(1..=MAX_MULTIPLIER).contains(&multiplier) {
+            panic!("Invalid multiplier");
+        }
+        env.storage().instance().set(&DataKey::UserMultiplier(user.clone()), &multiplier);
+        env.events().publish((Symbol::new(&env, "user_multiplier_updated"), user), multiplier);
+    }
+
+    /// Boosts the rewards of deposits made by holders of a partner token, or stops
+    /// boosting them when `boost` is `None`. Open positions keep their multiplier.
+    pub fn update_partner_boost(env: Env, caller: Address, boost: Option<PartnerBoost>) {
+        caller.require_auth();
+        Self::require_owner(&env, caller);
+        match boost {
+            Some(boost) => {
+                if boost.min_balance <= 0 || !(1..=MAX_MULTIPLIER).contains(&boost.multiplier) {
+                    panic!("Invalid partner boost");
+                }
+                env.storage().instance().set(&DataKey::PartnerBoost, &boost);
+            }
+            None => env.storage().instance().remove(&DataKey::PartnerBoost),
+        }
+    }
+
+    /// Caps the total deposits of `token` at `tvl_cap` and what a single depositor can
+    /// hold at `user_cap`. Deposits already made above a lowered cap are kept.
+    pub fn update_deposit_caps(env: Env, caller: Address, token: Address, tvl_cap: i128, user_cap: i128) {
+        caller.require_auth();
+        Self::require_owner(&env, caller);
+        if tvl_cap <= 0 || user_cap <= 0 {
+            panic!("Invalid cap");
+        }
+        env.storage().instance().set(&DataKey::DepositCaps(token.clone()), &DepositCaps { tvl_cap, user_cap });
+        env.events().publish((Symbol::new(&env, "deposit_caps_updated"), token), (tvl_cap, user_cap));
+    }
+
+    /// Starts the timelock for moving every supplied token to the Blend pool `new_pool`.
+    pub fn propose_pool_migration(env: Env, caller: Address, token: Address, new_pool: Address) {
+        caller.require_auth();
+        Self::require_owner(&env, caller);
+        let migration = PendingMigration {
+            new_pool: new_pool.clone(),
+            executable_at: env.ledger().timestamp() + MIGRATION_TIMELOCK,
+        };
+        env.storage().instance().set(&DataKey::PendingMigration(token.clone()), &migration);
+        env.events().publish(
+            (Symbol::new(&env, "pool_migration_proposed"), token),
+            (new_pool, migration.executable_at),
+        );
+    }
+
+    pub fn cancel_pool_migration(env: Env, caller: Address, token: Address) {
+        caller.require_auth();
+        Self::require_owner(&env, caller);
+        env.storage().instance().remove(&DataKey::PendingMigration(token.clone()));
+    }
+
+    /// Withdraws every bToken the contract holds from the current Blend pool and
+    /// supplies the proceeds to `new_pool`, once the proposal's timelock expired.
+    /// Positions are rebased lazily so each keeps its share of the supply.
+    pub fn migrate_pool(env: Env, caller: Address, token: Address, new_pool: Address) {
+        caller.require_auth();
+        Self::require_owner(&env, caller);
+        let migration: PendingMigration = env.storage().instance()
+            .get(&DataKey::PendingMigration(token.clone()))
+            .unwrap_or_else(|| panic!("Migration not proposed"));
+        if migration.new_pool != new_pool {
+            panic!("Migration not proposed");
+        }
+        if env.ledger().timestamp() < migration.executable_at {
+            panic!("Migration timelocked");
+        }
+        if !Self::withdrawal_queue(&env, &token).is_empty() {
+            panic!("Withdrawals queued");
+        }
+        let pool_address: Address = env.storage().instance().get(&DataKey::PoolAddress(token.clone())).unwrap();
+        if Self::pool_allocations(&env, &token).iter().any(|allocation| allocation.pool == pool_address) {
+            panic!("Pool in allocations");
+        }
+        // Collect the old pool's emissions before leaving it; a pool left in an
+        // emergency exit is migrated out of the vault
+        Self::harvest(&env, &token);
+        let old_pool = Adapter::load(&env, &token);
+        let b_tokens_before = old_pool.balance(&env);
+        let amount = old_pool.value(&env, b_tokens_before);
+        old_pool.withdraw(&env, amount).unwrap_or_else(|| panic!("Insufficient liquidity"));
+
+        let old_pool_address: Address = env.storage().instance().get(&DataKey::PoolAddress(token.clone())).unwrap();
+        env.storage().instance().set(&DataKey::PoolAddress(token.clone()), &new_pool);
+        env.storage().instance().set(&DataKey::YieldSource(token.clone()), &YieldSourceKind::Blend);
+        env.storage().instance().remove(&DataKey::PendingMigration(token.clone()));
+        env.storage().instance().remove(&DataKey::DepositsHalted(token.clone()));
+        if b_tokens_before == 0 {
+            env.events().publish(
+                (Symbol::new(&env, "pool_migrated"), token.clone()),
+                (old_pool_address, new_pool, 0i128, 0i128),
+            );
+            return;
+        }
+        let b_tokens_after = Adapter::load(&env, &token).supply(&env, amount);
+        Self::rebase_pool(&env, &token, b_tokens_before, b_tokens_after);
+
+        env.events().publish(
+            (Symbol::new(&env, "pool_migrated"), token.clone()),
+            (old_pool_address, new_pool, amount, b_tokens_after),
+        );
+    }
+
+    /// Withdraws everything supplied to the configured Blend pool and to the allocation
+    /// pools once they are on ice or frozen. The configured pool's funds stay idle in the
+    /// contract and an allocation pool's move to the configured yield source. Deposits
+    /// halt until the owner migrates or sets a yield source or allocations. Open to anyone.
+    pub fn emergency_exit(env: Env, caller: Address, token: Address) {
+        caller.require_auth();
+        Self::harvest(&env, &token);
+        let mut exited = false;
+
+        let pool = Adapter::load(&env, &token);
+        if matches!(pool, Adapter::Blend(_)) && pool.is_degraded(&env) {
+            let b_tokens_before = pool.balance(&env);
+            let amount = pool.value(&env, b_tokens_before);
+            pool.withdraw(&env, amount).unwrap_or_else(|| panic!("Insufficient liquidity"));
+
+            env.storage().instance().set(&DataKey::YieldSource(token.clone()), &YieldSourceKind::Vault);
+            if b_tokens_before > 0 {
+                let b_tokens_after = Adapter::load(&env, &token).supply(&env, amount);
+                Self::rebase_pool(&env, &token, b_tokens_before, b_tokens_after);
+            }
+
+            let pool_address: Address = env.storage().instance().get(&DataKey::PoolAddress(token.clone())).unwrap();
+            env.events().publish(
+                (Symbol::new(&env, "emergency_exit"), caller.clone()),
+                (token.clone(), pool_address, amount),
+            );
+            exited = true;
+        }
+
+        let mut allocations = Self::pool_allocations(&env, &token);
+        let mut i = 0;
+        while i < allocations.len() {
+            let allocation = allocations.get(i).unwrap();
+            if !Adapter::blend(&token, allocation.pool.clone()).is_degraded(&env) {
+                i += 1;
+                continue;
+            }
+            let amount = Self::exit_allocation_pool(&env, &token, &allocation.pool);
+            allocations.remove(i);
+            env.events().publish(
+                (Symbol::new(&env, "emergency_exit"), caller.clone()),
+                (token.clone(), allocation.pool, amount),
+            );
+            exited = true;
+        }
+        if !exited {
+            panic!("Pool is healthy");
+        }
+        env.storage().instance().set(&DataKey::PoolAllocations(token.clone()), &allocations);
+        env.storage().instance().set(&DataKey::DepositsHalted(token.clone()), &true);
+    }
+
+    /// Withdraws everything the contract holds in the allocation pool `pool` and
+    /// supplies it to the configured yield source, where its positions are rebased
+    /// lazily. Returns the amount moved.
+    fn exit_allocation_pool(env: &Env, token: &Address, pool: &Address) -> i128 {
+        let source = Adapter::blend(token, pool.clone());
+        let b_tokens_before = source.balance(env);
+        let amount = source.value(env, b_tokens_before);
+        source.withdraw(env, amount).unwrap_or_else(|| panic!("Insufficient liquidity"));
+        let pool_b_tokens: i128 = env.storage().instance().get(&DataKey::PoolBTokens(token.clone(), pool.clone())).unwrap_or(0);
+        env.storage().instance().remove(&DataKey::PoolBTokens(token.clone(), pool.clone()));
+        env.storage().instance().remove(&DataKey::PoolPositions(token.clone(), pool.clone()));
+        if b_tokens_before <= 0 {
+            return 0;
+        }
+
+        // The positions' share joins the configured source's total, rounding dust
+        // goes to the protocol
+        let b_tokens_after = Adapter::load(env, token).supply(env, amount);
+        let rebased = pool_b_tokens.fixed_mul_floor(env, &b_tokens_after, &b_tokens_before);
+        let total_b_tokens: i128 = env.storage().instance().get(&DataKey::TotalBTokens(token.clone())).unwrap_or(0);
+        env.storage().instance().set(&DataKey::TotalBTokens(token.clone()), &(total_b_tokens + rebased));
+        let protocol_b_tokens: i128 = env.storage().instance().get(&DataKey::ProtocolBTokens(token.clone())).unwrap_or(0);
+        env.storage().instance().set(&DataKey::ProtocolBTokens(token.clone()), &(protocol_b_tokens + b_tokens_after - rebased));
+
+        let pool_epoch: u32 = env.storage().instance().get(&DataKey::PoolEpoch(token.clone())).unwrap_or(0);
+        let exit = PoolExit { b_tokens_before, b_tokens_after, pool_epoch };
+        env.storage().instance().set(&DataKey::PoolExits(token.clone(), pool.clone()), &exit);
+
+        // Queued withdrawals are redeemed from the configured source
+        let mut queue = Self::withdrawal_queue(env, token);
+        for i in 0..queue.len() {
+            let mut withdrawal = queue.get(i).unwrap();
+            if withdrawal.pool.as_ref() == Some(pool) && !withdrawal.redeemed {
+                withdrawal.b_tokens = withdrawal.b_tokens.fixed_mul_floor(env, &b_tokens_after, &b_tokens_before);
+                withdrawal.pool = None;
+                queue.set(i, withdrawal);
+            }
+        }
+        env.storage().instance().set(&DataKey::WithdrawalQueue(token.clone()), &queue);
+        amount
+    }
+
+    /// Selects where deposits are supplied. Only possible while the current yield
+    /// source holds nothing for the contract.
+    pub fn update_yield_source(env: Env, caller: Address, token: Address, kind: YieldSourceKind) {
+        caller.require_auth();
+        Self::require_owner(&env, caller);
+        if Adapter::load(&env, &token).balance(&env) > 0 {
+            panic!("Yield source in use");
+        }
+        env.storage().instance().set(&DataKey::YieldSource(token.clone()), &kind);
+        env.storage().instance().remove(&DataKey::DepositsHalted(token.clone()));
+    }
+
+    /// Shares, in basis points, of the early withdrawal fees and of the forfeited
+    /// interest that fund the insurance reserve.
+    pub fn update_insurance_shares(env: Env, caller: Address, token: Address, fee_share: i128, forfeit_share: i128) {
+        caller.require_auth();
+        Self::require_owner(&env, caller);
+        let basis_points: i128 = env.storage().instance().get(&DataKey::BasisPoints).unwrap();
+        if !(0..=basis_points).contains(&fee_share) || !(0..=basis_points).contains(&forfeit_share) {
+            panic!("Invalid share");
+        }
+        env.storage().instance().set(&DataKey::InsuranceFeeShare(token.clone()), &fee_share);
+        env.storage().instance().set(&DataKey::InsuranceForfeitShare(token.clone()), &forfeit_share);
+    }
+
+    pub fn add_lock_period(env: Env, caller: Address, token: Address, new_lock_period: u64) {
+        caller.require_auth();
+        Self::require_owner(&env, caller);
+        if env.storage().instance().has(&DataKey::LockPeriodConfigs(token.clone(), new_lock_period)) {
+            panic!("Lock period already supported");
+        }
+        env.storage().instance().set(&DataKey::LockPeriodConfigs(token.clone(), new_lock_period), &Self::default_lock_period(&env));
+        // A period added back after its removal keeps its round numbering
+        if !env.storage().instance().has(&DataKey::RoundSchedules(token.clone(), new_lock_period)) {
+            Self::schedule_rounds(&env, &token, new_lock_period);
+        }
+        let mut lock_periods = Self::lock_periods(&env, &token);
+        lock_periods.push_back(new_lock_period);
+        env.storage().instance().set(&DataKey::LockPeriods(token.clone()), &lock_periods);
+    }
+
+    /// Stops new deposits into `period`. Open positions keep earning and can still be
+    /// withdrawn and claim their rewards.
+    pub fn disable_lock_period(env: Env, caller: Address, token: Address, period: u64) {
+        caller.require_auth();
+        Self::require_owner(&env, caller);
+        let mut lock_period = Self::active_lock_period(&env, &token, period);
+        lock_period.status = LockPeriodStatus::Disabled;
+        env.storage().instance().set(&DataKey::LockPeriodConfigs(token.clone(), period), &lock_period);
+        env.events().publish((Symbol::new(&env, "lock_period_disabled"), token), period);
+    }
+
+    /// Drops `period` once no deposits are left in it and no rewards are waiting for
+    /// its running or next round, in the deposit token, a bonus token or a stream.
+    /// Leftovers of past rounds are picked up again if the period is added back.
+    pub fn remove_lock_period(env: Env, caller: Address, token: Address, period: u64) {
+        caller.require_auth();
+        Self::require_owner(&env, caller);
+        Self::load_lock_period(&env, &token, period);
+        if Self::load_period(&env, &token, period).total_deposits != 0 {
+            panic!("Lock period in use");
+        }
+        // Queued withdrawals still credit their forfeits to the period once redeemed
+        if Self::withdrawal_queue(&env, &token).iter().any(|withdrawal| withdrawal.lock_period == period) {
+            panic!("Lock period in use");
+        }
+        let now = env.ledger().timestamp();
+        Self::accrue_streams(&env, &token, period, now);
+        let streams: Vec<RewardStream> = env.storage().instance()
+            .get(&DataKey::RewardStreams(token.clone(), period))
+            .unwrap_or(Vec::new(&env));
+        if !streams.is_empty() {
+            panic!("Reward streams active");
+        }
+        let next_round_id = Self::next_round(&env, &token, period, now);
+        for round_id in next_round_id.saturating_sub(1)..=next_round_id {
+            let round = Self::load_round(&env, &token, period, round_id);
+            if round.reward_pool != 0 || round.undistributed_rewards != 0 {
+                panic!("Rewards pending");
+            }
+            for reward_token in Self::reward_tokens(&env).iter() {
+                let index = Self::load_round_rewards(&env, &token, period, round_id, &reward_token);
+                if index.reward_pool != 0 || index.undistributed_rewards != 0 {
+                    panic!("Rewards pending");
+                }
+            }
+        }
+        env.storage().instance().remove(&DataKey::LockPeriodConfigs(token.clone(), period));
+        let mut lock_periods = Self::lock_periods(&env, &token);
+        if let Some(index) = lock_periods.first_index_of(period) {
+            lock_periods.remove(index);
+        }
+        env.storage().instance().set(&DataKey::LockPeriods(token.clone()), &lock_periods);
+        env.events().publish((Symbol::new(&env, "lock_period_removed"), token), period);
+    }
+
+    pub fn update_round_open_window(env: Env, caller: Address, token: Address, period: u64, open_window: u64) {
+        caller.require_auth();
+        Self::require_owner(&env, caller);
+        let mut schedule = Self::load_round_schedule(&env, &token, period);
+        Self::check_round_schedule(&env, period, open_window);
+        schedule.open_window = open_window;
+        env.storage().instance().set(&DataKey::RoundSchedules(token.clone(), period), &schedule);
+    }
+
+    // ---------- View functions ----------
+    /// Breaks down what withdrawing the position at `at_timestamp` would pay, valuing
+    /// it at the live Blend reserve. Rewards include what the period's streams release
+    /// until then and the position's share of the emissions not distributed yet, but
+    /// not the BLND Blend has yet to be claimed. A queued withdrawal is shown as it was
+    /// settled, with its fees withheld in `early_fee` and its bonus rewards already paid.
+    pub fn preview_withdraw(env: Env, deposit_id: String, at_timestamp: u64) -> WithdrawPreview {
+        let mut position: Position = env.storage().instance().get(&DataKey::Positions(deposit_id.clone()))
+            .unwrap_or_else(|| panic!("Position not found"));
+        let token = position.token.clone();
+        let mut bonus_rewards = Map::new(&env);
+        for reward_token in Self::reward_tokens(&env).iter() {
+            bonus_rewards.set(reward_token, 0);
+        }
+
+        if position.frozen {
+            let withdrawal = Self::withdrawal_queue(&env, &token).iter()
+                .find(|withdrawal| withdrawal.deposit_id == deposit_id)
+                .unwrap_or_else(|| panic!("Withdrawal not queued"));
+            let covered = withdrawal.loss.min(Self::get_insurance_buffer(env.clone(), token.clone()));
+            let early_fee = withdrawal.protocol_fee + withdrawal.insured;
+            let withheld = early_fee + withdrawal.forfeit;
+            return WithdrawPreview {
+                principal: position.amount,
+                interest: (withdrawal.payout - withdrawal.reward + withheld - position.amount).max(0),
+                early_fee,
+                forfeited_interest: withdrawal.forfeit,
+                reward: withdrawal.reward,
+                bonus_rewards,
+                emissions: 0,
+                payout: withdrawal.payout + covered,
+            };
+        }
+
+        Self::rebase_position(&env, &mut position);
+        let amount = Adapter::for_pool(&env, &token, &position.pool).value(&env, position.b_tokens);
+        let interest = (amount - position.amount).max(0);
+        let loss = (position.amount - amount).max(0);
+        let covered = loss.min(Self::get_insurance_buffer(env.clone(), token.clone()));
+
+        let mut preview = WithdrawPreview {
+            principal: position.amount,
+            interest,
+            early_fee: 0,
+            forfeited_interest: 0,
+            reward: 0,
+            bonus_rewards,
+            emissions: 0,
+            payout: amount + covered,
+        };
+        if at_timestamp < position.finalization_time {
+            let early_fee: i128 = env.storage().instance().get(&DataKey::EarlyWithdrawalFee(token.clone())).unwrap();
+            preview.early_fee = interest.fixed_mul_ceil(&env, &early_fee, &10000);
+            preview.forfeited_interest = interest - preview.early_fee;
+            preview.payout -= interest;
+        } else {
+            // Accrue the round in memory like a withdrawal at `at_timestamp` would
+            let now = at_timestamp.max(env.ledger().timestamp());
+            let mut round = Self::load_round(&env, &token, position.lock_period, position.round);
+            let streams: Vec<RewardStream> = env.storage().instance()
+                .get(&DataKey::RewardStreams(token.clone(), position.lock_period))
+                .unwrap_or(Vec::new(&env));
+            for (round_id, from, released) in Self::stream_releases(&env, &token, position.lock_period, &streams, now).iter() {
+                if round_id == position.round {
+                    round.reward_pool += released;
+                    Self::distribute_rewards(&env, &mut round, released, from);
+                }
+            }
+            Self::settle_round(&env, &mut round, now);
+            preview.reward = Self::calculate_reward(&env, &round, &position);
+            for (reward_token, _, bonus) in Self::accrue_bonus_rewards(&env, &position, &round, now).iter() {
+                preview.bonus_rewards.set(reward_token, bonus);
+            }
+            let undistributed: i128 = env.storage().instance().get(&DataKey::UndistributedEmissions(token.clone())).unwrap_or(0);
+            let total_deposits: i128 = env.storage().instance().get(&DataKey::TotalDeposits(token.clone())).unwrap_or(0);
+            let emissions_per_share: i128 = env.storage().instance().get(&DataKey::EmissionsPerShare(token.clone())).unwrap_or(0);
+            let pending_per_share = undistributed.fixed_div_floor(&env, &total_deposits, &SCALAR_12);
+            preview.emissions = (position.amount.fixed_mul_floor(&env, &(emissions_per_share + pending_per_share), &SCALAR_12)
+                - position.emissions_debt).max(0);
+            preview.payout += preview.reward;
+        }
+        preview
+    }
+
+    /// bTokens the live Blend reserve would mint for depositing `amount` into `period`,
+    /// and the round the deposit would join and its maturity.
+    pub fn preview_deposit(env: Env, token: Address, amount: i128, period: u64) -> DepositPreview {
+        Self::active_lock_period(&env, &token, period);
+        let round_id = Self::next_round(&env, &token, period, env.ledger().timestamp());
+        let yield_source = Adapter::for_pool(&env, &token, &Self::route_deposit(&env, &token, amount));
+        DepositPreview {
+            b_tokens: amount.fixed_div_floor(&env, &yield_source.rate(&env), &SCALAR_12),
+            round: round_id,
+            maturity: Self::load_round(&env, &token, period, round_id).end_time,
+        }
+    }
+
+    pub fn get_position(env: Env, deposit_id: String) -> Option<Position> {
+        let mut position: Position = env.storage().instance().get(&DataKey::Positions(deposit_id))?;
+        Self::rebase_position(&env, &mut position);
+        Some(position)
+    }
+
+    pub fn get_pool_allocations(env: Env, token: Address) -> Vec<PoolAllocation> {
+        Self::pool_allocations(&env, &token)
+    }
+
+    /// Current supply rate of the allocation pool `pool`, scaled by SCALAR_7.
+    pub fn get_pool_supply_rate(env: Env, token: Address, pool: Address) -> i128 {
+        Adapter::blend(&token, pool).supply_rate(&env)
+    }
+
+    pub fn get_rebalance_config(env: Env, token: Address) -> Option<RebalanceConfig> {
+        env.storage().instance().get(&DataKey::RebalanceConfig(token))
+    }
+
+    /// bTokens held in the allocation pool `pool` on behalf of open positions.
+    pub fn get_pool_b_tokens(env: Env, token: Address, pool: Address) -> i128 {
+        env.storage().instance().get(&DataKey::PoolBTokens(token.clone(), pool)).unwrap_or(0)
+    }
+
+    pub fn get_pending_migration(env: Env, token: Address) -> Option<PendingMigration> {
+        env.storage().instance().get(&DataKey::PendingMigration(token.clone()))
+    }
+
+    /// Catalog of the lock periods of `token`, with their terms and current deposits
+    /// and reward pool.
+    pub fn get_lock_periods(env: Env, token: Address) -> Vec<LockPeriodInfo> {
+        let mut lock_periods = Vec::new(&env);
+        for period in Self::lock_periods(&env, &token).iter() {
+            lock_periods.push_back(LockPeriodInfo {
+                period,
+                config: Self::load_lock_period(&env, &token, period),
+                data: Self::load_period(&env, &token, period),
+            });
+        }
+        lock_periods
+    }
+
+    pub fn get_user_multiplier(env: Env, user: Address) -> u32 {
+        env.storage().instance().get(&DataKey::UserMultiplier(user)).unwrap_or(10000)
+    }
+
+    pub fn get_partner_boost(env: Env) -> Option<PartnerBoost> {
+        env.storage().instance().get(&DataKey::PartnerBoost)
+    }
+
+    /// Multiplier, in basis points, a deposit by `user` into `period` would get now.
+    pub fn get_reward_multiplier(env: Env, token: Address, period: u64, user: Address) -> u32 {
+        Self::reward_multiplier(&env, &user, &Self::load_lock_period(&env, &token, period))
+    }
+
+    pub fn get_deposit_caps(env: Env, token: Address) -> DepositCaps {
+        Self::deposit_caps(&env, &token)
+    }
+
+    /// Principal `user` holds in open positions of `token`.
+    pub fn get_user_deposits(env: Env, token: Address, user: Address) -> i128 {
+        Self::user_deposits(&env, &token, &user)
+    }
+
+    /// How much `user` could still deposit into `period` before reaching the period's
+    /// cap, the token's TVL cap and the per-user cap.
+    pub fn get_remaining_capacity(env: Env, token: Address, period: u64, user: Address) -> DepositCapacity {
+        Self::deposit_capacity(&env, &token, period, &user)
+    }
+
+    pub fn get_period_data(env: Env, token: Address, period: u64) -> Option<Period> {
+        env.storage().instance().get(&DataKey::Periods(token.clone(), period))
+    }
+
+    pub fn get_round_schedule(env: Env, token: Address, period: u64) -> Option<RoundSchedule> {
+        env.storage().instance().get(&DataKey::RoundSchedules(token.clone(), period))
+    }
+
+    /// Round that deposits into `period` currently join.
+    pub fn get_current_round(env: Env, token: Address, period: u64) -> u32 {
+        Self::next_round(&env, &token, period, env.ledger().timestamp())
+    }
+
+    pub fn get_round(env: Env, token: Address, period: u64, round_id: u32) -> Option<Round> {
+        env.storage().instance().get(&DataKey::Rounds(token.clone(), period, round_id))
+    }
+
+    pub fn get_yield_source(env: Env, token: Address) -> YieldSourceKind {
+        env.storage().instance().get(&DataKey::YieldSource(token.clone())).unwrap_or(YieldSourceKind::Blend)
+    }
+
+    pub fn get_withdrawal_queue(env: Env, token: Address) -> Vec<QueuedWithdrawal> {
+        Self::withdrawal_queue(&env, &token)
+    }
+
+    /// Current value in the deposit token of the insurance reserve.
+    pub fn get_insurance_buffer(env: Env, token: Address) -> i128 {
+        Self::b_tokens_value(&env, &token, Self::get_insurance_b_tokens(env.clone(), token.clone()))
+    }
+
+    /// bTokens held in Blend on behalf of the insurance reserve.
+    pub fn get_insurance_b_tokens(env: Env, token: Address) -> i128 {
+        env.storage().instance().get(&DataKey::InsuranceBTokens(token.clone())).unwrap_or(0)
+    }
+
+    /// bTokens held in Blend on behalf of open positions.
+    pub fn get_total_b_tokens(env: Env, token: Address) -> i128 {
+        env.storage().instance().get(&DataKey::TotalBTokens(token.clone())).unwrap_or(0)
+    }
+
+    /// bTokens held in Blend on behalf of the protocol: compounded rewards and rounding dust.
+    pub fn get_protocol_b_tokens(env: Env, token: Address) -> i128 {
+        env.storage().instance().get(&DataKey::ProtocolBTokens(token.clone())).unwrap_or(0)
+    }
+
+    /// Layout the stored data uses, 0 for pools initialized before it was versioned.
+    pub fn get_storage_version(env: Env) -> u32 {
+        env.storage().instance().get(&DataKey::StorageVersion).unwrap_or(0)
+    }
+
+    /// Tokens that can be deposited, each with its own Blend pool and lock periods.
+    pub fn get_tokens(env: Env) -> Vec<Address> {
+        Self::tokens(&env)
+    }
+
+    pub fn get_protocol_fees(env: Env, token: Address) -> i128 {
+        env.storage().instance().get(&DataKey::ProtocolFees(token)).unwrap_or(0)
+    }
+
+    pub fn get_reward_tokens(env: Env) -> Vec<Address> {
+        Self::reward_tokens(&env)
+    }
+
+    pub fn get_round_rewards(env: Env, token: Address, period: u64, round_id: u32, reward_token: Address) -> Option<RewardIndex> {
+        env.storage().instance().get(&DataKey::RoundRewards(token.clone(), period, round_id, reward_token))
+    }
+
+    pub fn get_reward_streams(env: Env, token: Address, period: u64) -> Vec<RewardStream> {
+        env.storage().instance().get(&DataKey::RewardStreams(token.clone(), period)).unwrap_or(Vec::new(&env))
+    }
+
+    /// Amount of the period's active reward streams released so far.
+    pub fn get_vested_stream_rewards(env: Env, token: Address, period: u64) -> i128 {
+        let now = env.ledger().timestamp();
+        Self::get_reward_streams(env.clone(), token, period).iter()
+            .map(|stream| Self::stream_released_at(&env, &stream, now))
+            .sum()
+    }
+
+    /// Amount of the period's active reward streams still to be released.
+    pub fn get_pending_stream_rewards(env: Env, token: Address, period: u64) -> i128 {
+        let now = env.ledger().timestamp();
+        Self::get_reward_streams(env.clone(), token, period).iter()
+            .map(|stream| stream.amount - Self::stream_released_at(&env, &stream, now))
+            .sum()
+    }
+}
+
+mod catalog {
+    // The argument helpers `contractimpl` generates don't carry the functions' own
+    // lint attributes, so the functions taking more arguments live here
+    #![allow(clippy::too_many_arguments)]
+    use super::*;
+
+    #[contractimpl]
+    impl VaquitaPool {
+        /// Sets the catalog entry of `period`: its display name, the deposit limits and
+        /// the bonus multiplier in basis points. The status is left as is.
+        pub fn update_lock_period(
+            env: Env,
+            caller: Address,
+            token: Address,
+            period: u64,
+            name: String,
+            min_deposit: i128,
+            max_deposit: i128,
+            cap: i128,
+            bonus_multiplier: u32,
+        ) {
+            caller.require_auth();
+            Self::require_owner(&env, caller);
+            let mut lock_period = Self::load_lock_period(&env, &token, period);
+            if min_deposit <= 0 || max_deposit < min_deposit || cap <= 0 || !(1..=MAX_MULTIPLIER).contains(&bonus_multiplier) {
+                panic!("Invalid lock period");
+            }
+            lock_period.name = name;
+            lock_period.min_deposit = min_deposit;
+            lock_period.max_deposit = max_deposit;
+            lock_period.cap = cap;
+            lock_period.bonus_multiplier = bonus_multiplier;
+            env.storage().instance().set(&DataKey::LockPeriodConfigs(token.clone(), period), &lock_period);
+            env.events().publish((Symbol::new(&env, "lock_period_updated"), token), (period, lock_period));
+        }
+    }
+}
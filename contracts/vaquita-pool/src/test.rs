@@ -6,6 +6,7 @@ pub const REWARD_THRESHOLD: i128 = 1;
 pub const SCALAR_7: i128 = 1_0000000;
 pub const ONE_DAY_LEDGERS: u32 = 17280; 
 use sep_41_token::testutils::MockTokenClient;
+#[allow(unused_imports)]
 use soroban_sdk::{
     testutils::{BytesN as _, Ledger as _, LedgerInfo, Address as _},
     vec, Address, BytesN, Env, IntoVal, String, Symbol, Val, Vec,
@@ -22,6 +23,7 @@ pub mod backstop {
 }
 use backstop::{Client as BackstopClient};
 
+#[allow(clippy::needless_borrow, clippy::too_many_arguments)]
 pub fn create_backstop<'a>(
     e: &Env,
     contract_id: &Address,
@@ -53,11 +55,13 @@ pub mod emitter {
 
 // Pool Factory
 pub mod pool_factory {
+    #![allow(clippy::too_many_arguments)]
     soroban_sdk::contractimport!(file = "src/external_wasms/blend/pool_factory.wasm");
 }
 
 use pool_factory::{Client as PoolFactoryClient, PoolInitMeta};
 
+#[allow(clippy::needless_borrow)]
 pub fn create_pool_factory<'a>(
     e: &Env,
     contract_id: &Address,
@@ -68,6 +72,7 @@ pub fn create_pool_factory<'a>(
 }
 
 pub mod pool {
+    #![allow(clippy::too_many_arguments)]
     soroban_sdk::contractimport!(file = "src/external_wasms/blend/pool.wasm");
 }
 use pool::{
@@ -79,7 +84,7 @@ pub struct BlendFixture<'a> {
     // pub dummy: Address<'a>,
     pub backstop: backstop::Client<'a>,
     pub emitter: emitter::Client<'a>,
-    // pub backstop_token: comet::Client<'a>,
+    pub backstop_token: comet::Client<'a>,
     pub pool_factory: pool_factory::Client<'a>,
 }
 
@@ -95,6 +100,7 @@ impl<'a> BlendFixture<'a> {
     /// * `deployer` - The address of the deployer
     /// * `blnd` - The address of the BLND token
     /// * `usdc` - The address of the USDC token
+    #[allow(clippy::needless_borrow, clippy::inconsistent_digit_grouping, clippy::zero_prefixed_literal)]
     pub fn deploy(
         env: &Env,
         deployer: &Address,
@@ -169,12 +175,18 @@ impl<'a> BlendFixture<'a> {
         BlendFixture {
             backstop: backstop_client,
             emitter: emitter_client,
-            // backstop_token: comet_client,
+            backstop_token: comet_client,
             pool_factory: pool_factory_client,
         }
     }
 }
 
+#[allow(
+    clippy::needless_borrow,
+    clippy::needless_return,
+    clippy::inconsistent_digit_grouping,
+    clippy::zero_prefixed_literal
+)]
 pub(crate) fn create_blend_pool(
     e: &Env,
     blend_fixture: &BlendFixture,
@@ -356,10 +368,26 @@ pub fn create_mock_oracle<'a>(e: &Env) -> (Address, MockPriceOracleClient<'a>) {
 
 /// Mock pool to test b_rate updates
 pub mod mockpool {
+    #![allow(clippy::too_many_arguments)]
 
-    use soroban_sdk::{contract, contractimpl, contracttype, symbol_short, Address, Env, Symbol};
+    use soroban_sdk::{
+        contract, contractimpl, contracttype, symbol_short, token::Client as TokenClient, Address,
+        Env, Map, Symbol, Vec,
+    };
 
     const BRATE: Symbol = symbol_short!("b_rate");
+    const ASSETS: Symbol = symbol_short!("assets");
+    const CONFIG: Symbol = symbol_short!("config");
+    const DATA: Symbol = symbol_short!("data");
+    const STATUS: Symbol = symbol_short!("status");
+    const SCALAR_12: i128 = 1_000_000_000_000;
+
+    #[derive(Clone)]
+    #[contracttype]
+    pub enum MockPoolKey {
+        Supply(Address, Address),
+    }
+
     #[derive(Clone, Debug)]
     #[contracttype]
     pub struct Reserve {
@@ -399,6 +427,32 @@ pub mod mockpool {
         pub last_time: u64, // the last block the data was updated
     }
 
+    #[derive(Clone, Debug)]
+    #[contracttype]
+    pub struct PoolConfig {
+        pub bstop_rate: u32,
+        pub max_positions: u32,
+        pub min_collateral: i128,
+        pub oracle: Address,
+        pub status: u32,
+    }
+
+    #[derive(Clone, Debug)]
+    #[contracttype]
+    pub struct Request {
+        pub request_type: u32,
+        pub address: Address,
+        pub amount: i128,
+    }
+
+    #[derive(Clone, Debug)]
+    #[contracttype]
+    pub struct Positions {
+        pub liabilities: Map<u32, i128>,
+        pub collateral: Map<u32, i128>,
+        pub supply: Map<u32, i128>,
+    }
+
     #[contract]
     pub struct MockPool;
 
@@ -412,17 +466,202 @@ pub mod mockpool {
             e.storage().instance().set(&BRATE, &b_rate);
         }
 
-        /// Note: We're only interested in the `b_rate`
+        /// Lends `amount` of the pool's `asset` out to `to`, leaving less liquidity for withdrawals
+        pub fn lend(e: Env, asset: Address, to: Address, amount: i128) {
+            TokenClient::new(&e, &asset).transfer(&e.current_contract_address(), &to, &amount);
+        }
+
+        /// Sets the interest rate curve and the supply and borrows that determine
+        /// the reserve's utilization, with a `d_rate` of 1
+        pub fn set_interest(
+            e: Env,
+            util: u32,
+            r_base: u32,
+            r_one: u32,
+            r_two: u32,
+            r_three: u32,
+            ir_mod: i128,
+            b_supply: i128,
+            d_supply: i128,
+        ) {
+            let config = ReserveConfig { util, r_base, r_one, r_two, r_three, ..Self::config(&e) };
+            let data = ReserveData { d_rate: SCALAR_12, ir_mod, b_supply, d_supply, ..Default::default() };
+            e.storage().instance().set(&CONFIG, &config);
+            e.storage().instance().set(&DATA, &data);
+        }
+
+        pub fn set_reserve_limits(e: Env, enabled: bool, supply_cap: i128) {
+            let config = ReserveConfig { enabled, supply_cap, ..Self::config(&e) };
+            e.storage().instance().set(&CONFIG, &config);
+        }
+
+        pub fn set_status(e: Env, status: u32) {
+            e.storage().instance().set(&STATUS, &status);
+        }
+
+        /// Note: We're only interested in the `b_rate`, the index and what `set_interest` set
         pub fn get_reserve(e: Env, reserve: Address) -> Reserve {
-            let mut r_data = ReserveData::default();
+            let mut r_data: ReserveData = e.storage().instance().get(&DATA).unwrap_or_default();
             r_data.b_rate = e.storage().instance().get(&BRATE).unwrap_or(0);
+            let config = ReserveConfig { index: Self::reserve_index(&e, &reserve), ..Self::config(&e) };
             Reserve {
                 asset: reserve,
-                config: ReserveConfig::default(),
+                config,
                 data: r_data,
                 scalar: 0,
             }
         }
+
+        pub fn get_config(e: Env) -> PoolConfig {
+            PoolConfig {
+                bstop_rate: 1_000_000,
+                max_positions: 4,
+                min_collateral: 0,
+                oracle: e.current_contract_address(),
+                status: e.storage().instance().get(&STATUS).unwrap_or(1),
+            }
+        }
+
+        pub fn get_positions(e: Env, address: Address) -> Positions {
+            let mut supply = Map::new(&e);
+            for (index, asset) in Self::assets(&e).iter().enumerate() {
+                let b_tokens = Self::supply_of(&e, &address, &asset);
+                if b_tokens > 0 {
+                    supply.set(index as u32, b_tokens);
+                }
+            }
+            Positions {
+                liabilities: Map::new(&e),
+                collateral: Map::new(&e),
+                supply,
+            }
+        }
+
+        pub fn submit(e: Env, from: Address, spender: Address, to: Address, requests: Vec<Request>) -> Positions {
+            from.require_auth();
+            spender.require_auth();
+            Self::apply_requests(&e, &from, &spender, &to, &requests, false);
+            Self::get_positions(e, from)
+        }
+
+        pub fn submit_with_allowance(
+            e: Env,
+            from: Address,
+            spender: Address,
+            to: Address,
+            requests: Vec<Request>,
+        ) -> Positions {
+            from.require_auth();
+            spender.require_auth();
+            Self::apply_requests(&e, &from, &spender, &to, &requests, true);
+            Self::get_positions(e, from)
+        }
+    }
+
+    impl MockPool {
+        /// Enabled and uncapped unless configured otherwise
+        fn config(e: &Env) -> ReserveConfig {
+            e.storage().instance().get(&CONFIG).unwrap_or(ReserveConfig {
+                supply_cap: i128::MAX,
+                enabled: true,
+                ..Default::default()
+            })
+        }
+
+        /// Mirrors Blend's supply (0, 2) and withdraw (1, 3) requests, rounding in favor of the pool
+        fn apply_requests(e: &Env, from: &Address, spender: &Address, to: &Address, requests: &Vec<Request>, allowance: bool) {
+            let b_rate: i128 = e.storage().instance().get(&BRATE).unwrap();
+            let pool = e.current_contract_address();
+            for request in requests.iter() {
+                let token_client = TokenClient::new(e, &request.address);
+                let b_tokens = Self::supply_of(e, from, &request.address);
+                match request.request_type {
+                    0 | 2 => {
+                        let minted = request.amount * SCALAR_12 / b_rate;
+                        if minted == 0 {
+                            panic!("Invalid bToken mint amount");
+                        }
+                        if allowance {
+                            token_client.transfer_from(&pool, spender, &pool, &request.amount);
+                        } else {
+                            token_client.transfer(spender, &pool, &request.amount);
+                        }
+                        Self::set_supply(e, from, &request.address, b_tokens + minted);
+                    }
+                    1 | 3 => {
+                        let mut to_burn = (request.amount * SCALAR_12 + b_rate - 1) / b_rate;
+                        let mut tokens_out = request.amount;
+                        if to_burn > b_tokens {
+                            to_burn = b_tokens;
+                            tokens_out = b_tokens * b_rate / SCALAR_12;
+                        }
+                        Self::set_supply(e, from, &request.address, b_tokens - to_burn);
+                        token_client.transfer(&pool, to, &tokens_out);
+                    }
+                    _ => panic!("Unsupported request"),
+                }
+            }
+        }
+
+        fn assets(e: &Env) -> Vec<Address> {
+            e.storage().instance().get(&ASSETS).unwrap_or(Vec::new(e))
+        }
+
+        /// Reserves are indexed in the order they were first supplied
+        fn reserve_index(e: &Env, asset: &Address) -> u32 {
+            let assets = Self::assets(e);
+            assets.first_index_of(asset).unwrap_or(assets.len())
+        }
+
+        fn supply_of(e: &Env, user: &Address, asset: &Address) -> i128 {
+            e.storage()
+                .instance()
+                .get(&MockPoolKey::Supply(user.clone(), asset.clone()))
+                .unwrap_or(0)
+        }
+
+        fn set_supply(e: &Env, user: &Address, asset: &Address, b_tokens: i128) {
+            let mut assets = Self::assets(e);
+            if !assets.contains(asset) {
+                assets.push_back(asset.clone());
+                e.storage().instance().set(&ASSETS, &assets);
+            }
+            e.storage()
+                .instance()
+                .set(&MockPoolKey::Supply(user.clone(), asset.clone()), &b_tokens);
+        }
+    }
+}
+
+/// Deploys a `MockPool` quoting the given `b_rate`
+pub fn create_mock_pool<'a>(e: &Env, b_rate: i128) -> (Address, mockpool::MockPoolClient<'a>) {
+    let contract_id = e.register(mockpool::MockPool, (b_rate,));
+    (contract_id.clone(), mockpool::MockPoolClient::new(e, &contract_id))
+}
+
+/// Fixture for a `VaquitaPool` accepting a fresh `usdc` and supplying it to a `MockPool`.
+pub struct VaquitaFixture<'a> {
+    pub admin: Address,
+    pub usdc: MockTokenClient<'a>,
+    pub mock_pool: mockpool::MockPoolClient<'a>,
+    pub vaquita: crate::VaquitaPoolClient<'a>,
+}
+
+impl<'a> VaquitaFixture<'a> {
+    /// Deploys the pool with the given `lock_periods`, its `MockPool` quoting `b_rate`,
+    /// with auths mocked and the ledger at its default info
+    pub fn deploy(e: &Env, b_rate: i128, lock_periods: &[u64]) -> VaquitaFixture<'a> {
+        e.cost_estimate().budget().reset_unlimited();
+        e.mock_all_auths();
+        e.set_default_info();
+
+        let admin = Address::generate(e);
+        let usdc = MockTokenClient::new(e, &e.register_stellar_asset_contract_v2(admin.clone()).address());
+        let (pool, mock_pool) = create_mock_pool(e, b_rate);
+        let vaquita = crate::VaquitaPoolClient::new(e, &e.register(crate::VaquitaPool, ()));
+        vaquita.initialize(&admin, &usdc.address, &pool, &Vec::from_slice(e, lock_periods));
+
+        VaquitaFixture { admin, usdc, mock_pool, vaquita }
     }
 }
 
@@ -439,7 +678,19 @@ pub fn assert_approx_eq_rel(a: i128, b: i128, percentage: i128) {
     );
 }
 
+mod allocation;
+mod caps;
+mod emissions;
+mod health;
+mod limits;
+mod losses;
+mod migration;
+mod periods;
+mod queue;
+mod rewards;
+mod solvency;
 mod success;
+mod tokens;
 
 // pub(crate) fn create_usdc_token<'a>(
 //     e: &Env,
@@ -0,0 +1,168 @@
+#![cfg(test)]
+#![allow(clippy::inconsistent_digit_grouping)]
+use crate::test::{create_mock_pool, EnvTestUtils, VaquitaFixture, ONE_DAY_IN_SECONDS};
+use crate::{PoolAllocation, VaquitaPoolError};
+use soroban_sdk::testutils::{Address as _, Events};
+use soroban_sdk::{Address, Env, IntoVal, String, Symbol, Vec};
+
+const WEEK: u64 = 7 * ONE_DAY_IN_SECONDS;
+
+#[test]
+fn deposits_follow_target_weights_across_pools() {
+    let e = Env::default();
+    let VaquitaFixture { admin, usdc, vaquita, .. } = VaquitaFixture::deploy(&e, 1_000_000_000_000, &[WEEK]);
+    let alice = Address::generate(&e);
+    let (pool_a, mock_pool_a) = create_mock_pool(&e, 1_000_000_000_000);
+    let (pool_b, mock_pool_b) = create_mock_pool(&e, 1_000_000_000_000);
+    usdc.mint(&pool_a, &100_0000000);
+    usdc.mint(&alice, &1_000_0000000);
+
+    let allocations = Vec::from_array(
+        &e,
+        [
+            PoolAllocation { pool: pool_a.clone(), weight: 6000, cap: 300_0000000 },
+            PoolAllocation { pool: pool_b.clone(), weight: 4000, cap: 300_0000000 },
+        ],
+    );
+    vaquita.set_pool_allocations(&admin, &usdc.address, &allocations);
+
+    // Each deposit goes to the pool furthest below its share of the new total
+    for (id, expected_pool) in [("1", &pool_a), ("2", &pool_b), ("3", &pool_a)] {
+        vaquita.deposit(&alice, &usdc.address, &String::from_str(&e, id), &100_0000000, &WEEK);
+        let position = vaquita.get_position(&String::from_str(&e, id)).unwrap();
+        assert_eq!(position.pool, Some(expected_pool.clone()));
+    }
+    assert_eq!(vaquita.get_pool_b_tokens(&usdc.address, &pool_a), 200_0000000);
+    assert_eq!(vaquita.get_pool_b_tokens(&usdc.address, &pool_b), 100_0000000);
+    assert_eq!(vaquita.get_total_b_tokens(&usdc.address), 0);
+    assert_eq!(mock_pool_b.get_positions(&vaquita.address).supply.get(0), Some(100_0000000));
+
+    // No pool can take the deposit under its cap
+    let result = vaquita.try_deposit(&alice, &usdc.address, &String::from_str(&e, "4"), &250_0000000, &WEEK);
+    assert_eq!(result.err(), Some(Ok(VaquitaPoolError::AllocationCapReached.into())));
+
+    // Pools holding deposits cannot be dropped from the allocation
+    assert!(vaquita.try_set_pool_allocations(&admin, &usdc.address, &Vec::new(&e)).is_err());
+
+    // Withdrawals redeem from the pool the position was supplied to
+    mock_pool_a.set_b_rate(&1_100_000_000_000);
+    e.jump_time(2 * WEEK);
+    let payout = vaquita.preview_withdraw(&String::from_str(&e, "1"), &e.ledger().timestamp()).payout;
+    assert!(payout > 100_0000000);
+    vaquita.withdraw(&alice, &usdc.address, &String::from_str(&e, "1"));
+    assert_eq!(usdc.balance(&alice), 700_0000000 + payout);
+    assert_eq!(vaquita.get_pool_b_tokens(&usdc.address, &pool_a), 100_0000000);
+    assert_eq!(mock_pool_a.get_positions(&vaquita.address).supply.get(0), Some(100_0000000));
+    assert_eq!(vaquita.get_pool_b_tokens(&usdc.address, &pool_b), 100_0000000);
+}
+
+#[test]
+fn rebalance_moves_positions_toward_the_better_paying_pool() {
+    let e = Env::default();
+    let VaquitaFixture { admin, usdc, mock_pool: mock_primary_pool, vaquita } = VaquitaFixture::deploy(&e, 1_000_000_000_000, &[WEEK]);
+    let primary_pool = mock_primary_pool.address.clone();
+    let alice = Address::generate(&e);
+    let keeper = Address::generate(&e);
+    let (pool_a, _) = create_mock_pool(&e, 1_000_000_000_000);
+    let (pool_b, mock_pool_b) = create_mock_pool(&e, 1_000_000_000_000);
+    usdc.mint(&primary_pool, &10_0000000);
+    usdc.mint(&alice, &1_000_0000000);
+
+    vaquita.update_rebalance_config(&admin, &usdc.address, &50_0000000, &10, &0, &10);
+
+    // An early withdrawal leaves 5 USDC of protocol fees to pay keepers from
+    vaquita.update_early_withdrawal_fee(&admin, &usdc.address, &5000);
+    vaquita.deposit(&alice, &usdc.address, &String::from_str(&e, "early"), &100_0000000, &WEEK);
+    mock_primary_pool.set_b_rate(&1_100_000_000_000);
+    vaquita.withdraw(&alice, &usdc.address, &String::from_str(&e, "early"));
+
+    let allocate = |weights: [(Address, u32); 2]| {
+        let allocations = Vec::from_array(
+            &e,
+            weights.map(|(pool, weight)| PoolAllocation { pool, weight, cap: 1_000_0000000 }),
+        );
+        vaquita.set_pool_allocations(&admin, &usdc.address, &allocations);
+    };
+    allocate([(pool_a.clone(), 10000), (pool_b.clone(), 1)]);
+    for (id, amount) in [("1", 100_0000000), ("2", 40_0000000), ("3", 30_0000000)] {
+        vaquita.deposit(&alice, &usdc.address, &String::from_str(&e, id), &amount, &WEEK);
+    }
+    assert_eq!(vaquita.get_pool_b_tokens(&usdc.address, &pool_a), 170_0000000);
+    allocate([(pool_a.clone(), 5000), (pool_b.clone(), 5000)]);
+
+    // Pool B is below target but pays no more than pool A
+    assert!(vaquita.try_rebalance(&keeper, &usdc.address).is_err());
+
+    mock_pool_b.set_interest(&8_000_000, &100_000, &500_000, &5_000_000, &1_5000000, &1_0000000, &1_000_0000000, &500_0000000);
+    assert_eq!(vaquita.get_pool_supply_rate(&usdc.address, &pool_b), 185_625);
+
+    // Moves are capped at 50 USDC per call, so position "1" stays behind
+    assert_eq!(vaquita.rebalance(&keeper, &usdc.address), 40_0000000);
+    let rebalanced = e.events().all().iter()
+        .find(|(_, topics, _)| *topics == (Symbol::new(&e, "rebalanced"), keeper.clone()).into_val(&e));
+    assert!(rebalanced.is_some());
+    assert_eq!(usdc.balance(&keeper), 400000);
+    let position = vaquita.get_position(&String::from_str(&e, "2")).unwrap();
+    assert_eq!(position.pool, Some(pool_b.clone()));
+    assert_eq!(mock_pool_b.get_positions(&vaquita.address).supply.get(0), Some(40_0000000));
+
+    assert_eq!(vaquita.rebalance(&keeper, &usdc.address), 30_0000000);
+    assert_eq!(vaquita.get_pool_b_tokens(&usdc.address, &pool_a), 100_0000000);
+    assert_eq!(vaquita.get_pool_b_tokens(&usdc.address, &pool_b), 70_0000000);
+
+    // Within 15 USDC of target, no position is small enough to move
+    assert!(vaquita.try_rebalance(&keeper, &usdc.address).is_err());
+
+    // Moved positions withdraw from their new pool
+    e.jump_time(2 * WEEK);
+    vaquita.withdraw(&alice, &usdc.address, &String::from_str(&e, "3"));
+    assert_eq!(vaquita.get_pool_b_tokens(&usdc.address, &pool_b), 40_0000000);
+}
+
+#[test]
+fn rebalance_moves_several_positions_at_once_up_to_the_position_limit() {
+    let e = Env::default();
+    let VaquitaFixture { admin, usdc, vaquita, .. } = VaquitaFixture::deploy(&e, 1_000_000_000_000, &[WEEK]);
+    let alice = Address::generate(&e);
+    let keeper = Address::generate(&e);
+    let (pool_a, mock_pool_a) = create_mock_pool(&e, 1_000_000_000_000);
+    let (pool_b, mock_pool_b) = create_mock_pool(&e, 1_000_000_000_000);
+    usdc.mint(&alice, &40_0000000);
+
+    vaquita.update_rebalance_config(&admin, &usdc.address, &1_000_0000000, &2, &0, &0);
+    let allocate = |weights: [(Address, u32); 2]| {
+        let allocations = Vec::from_array(
+            &e,
+            weights.map(|(pool, weight)| PoolAllocation { pool, weight, cap: 1_000_0000000 }),
+        );
+        vaquita.set_pool_allocations(&admin, &usdc.address, &allocations);
+    };
+    allocate([(pool_a.clone(), 10000), (pool_b.clone(), 1)]);
+    for id in ["1", "2", "3", "4"] {
+        vaquita.deposit(&alice, &usdc.address, &String::from_str(&e, id), &10_0000000, &WEEK);
+    }
+    allocate([(pool_a.clone(), 2500), (pool_b.clone(), 7500)]);
+    mock_pool_b.set_interest(&8_000_000, &100_000, &500_000, &5_000_000, &1_5000000, &1_0000000, &1_000_0000000, &500_0000000);
+
+    // Only the two oldest positions are looked at, and they move together
+    assert_eq!(vaquita.rebalance(&keeper, &usdc.address), 20_0000000);
+    assert_eq!(mock_pool_a.get_positions(&vaquita.address).supply.get(0), Some(20_0000000));
+    assert_eq!(mock_pool_b.get_positions(&vaquita.address).supply.get(0), Some(20_0000000));
+    for id in ["1", "2"] {
+        let position = vaquita.get_position(&String::from_str(&e, id)).unwrap();
+        assert_eq!((position.pool, position.b_tokens), (Some(pool_b.clone()), 10_0000000));
+    }
+    assert_eq!(vaquita.get_position(&String::from_str(&e, "3")).unwrap().pool, Some(pool_a.clone()));
+
+    // The next call picks up where this one stopped, until pool A is at its target
+    assert_eq!(vaquita.rebalance(&keeper, &usdc.address), 10_0000000);
+    assert_eq!(vaquita.get_pool_b_tokens(&usdc.address, &pool_a), 10_0000000);
+    assert_eq!(vaquita.get_pool_b_tokens(&usdc.address, &pool_b), 30_0000000);
+    assert!(vaquita.try_rebalance(&keeper, &usdc.address).is_err());
+
+    e.jump_time(2 * WEEK);
+    for id in ["1", "2", "3", "4"] {
+        vaquita.withdraw(&alice, &usdc.address, &String::from_str(&e, id));
+    }
+    assert_eq!(usdc.balance(&alice), 40_0000000);
+}
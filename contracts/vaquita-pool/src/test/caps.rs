@@ -0,0 +1,43 @@
+#![cfg(test)]
+#![allow(clippy::inconsistent_digit_grouping)]
+use crate::test::{EnvTestUtils, VaquitaFixture, ONE_DAY_IN_SECONDS};
+use crate::{VaquitaPoolError, SCALAR_12};
+use soroban_sdk::testutils::Address as _;
+use soroban_sdk::{Address, Env, String};
+
+const WEEK: u64 = 7 * ONE_DAY_IN_SECONDS;
+
+#[test]
+fn deposits_stop_at_the_period_tvl_and_user_caps() {
+    let e = Env::default();
+    let VaquitaFixture { admin, usdc, vaquita, .. } = VaquitaFixture::deploy(&e, SCALAR_12, &[WEEK, 2 * WEEK]);
+    let alice = Address::generate(&e);
+    let bob = Address::generate(&e);
+    usdc.mint(&alice, &1_000_0000000);
+    usdc.mint(&bob, &1_000_0000000);
+
+    let name = String::from_str(&e, "Weekly");
+    vaquita.update_lock_period(&admin, &usdc.address, &WEEK, &name, &1, &i128::MAX, &120_0000000, &10000);
+    vaquita.update_deposit_caps(&admin, &usdc.address, &200_0000000, &100_0000000);
+    let deposit = |user: &Address, id: &str, amount: i128, period: u64| {
+        vaquita.try_deposit(user, &usdc.address, &String::from_str(&e, id), &amount, &period)
+    };
+
+    assert!(deposit(&alice, "alice-1", 80_0000000, WEEK).is_ok());
+    assert_eq!(deposit(&bob, "bob-1", 50_0000000, WEEK).err(), Some(Ok(VaquitaPoolError::PeriodCapReached.into())));
+    assert_eq!(deposit(&alice, "alice-2", 30_0000000, 2 * WEEK).err(), Some(Ok(VaquitaPoolError::UserCapReached.into())));
+    assert!(deposit(&bob, "bob-1", 100_0000000, 2 * WEEK).is_ok());
+    assert_eq!(deposit(&bob, "bob-2", 30_0000000, WEEK).err(), Some(Ok(VaquitaPoolError::TvlCapReached.into())));
+
+    let capacity = vaquita.get_remaining_capacity(&usdc.address, &WEEK, &alice);
+    assert_eq!((capacity.period, capacity.tvl, capacity.user), (40_0000000, 20_0000000, 20_0000000));
+    assert_eq!(vaquita.get_user_deposits(&usdc.address, &bob), 100_0000000);
+
+    // Withdrawing frees up room under every cap
+    e.jump_time(2 * WEEK);
+    vaquita.withdraw(&alice, &usdc.address, &String::from_str(&e, "alice-1"));
+    assert_eq!(vaquita.get_user_deposits(&usdc.address, &alice), 0);
+    let capacity = vaquita.get_remaining_capacity(&usdc.address, &WEEK, &alice);
+    assert_eq!((capacity.period, capacity.tvl, capacity.user), (120_0000000, 100_0000000, 100_0000000));
+    assert!(deposit(&alice, "alice-2", 100_0000000, WEEK).is_ok());
+}
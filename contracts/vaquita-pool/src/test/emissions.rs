@@ -0,0 +1,179 @@
+#![cfg(test)]
+#![allow(clippy::inconsistent_digit_grouping, clippy::zero_prefixed_literal)]
+use crate::test::{create_blend_pool, BlendFixture, EnvTestUtils, ONE_DAY_LEDGERS, REWARD_THRESHOLD, SCALAR_7};
+use crate::{VaquitaPool, VaquitaPoolClient};
+use sep_41_token::testutils::MockTokenClient;
+use soroban_sdk::testutils::Address as _;
+use soroban_sdk::{Address, Env, String, Vec};
+
+const WEEK: u64 = 604800;
+
+struct Setup<'a> {
+    admin: Address,
+    comet: Address,
+    blnd: MockTokenClient<'a>,
+    usdc: MockTokenClient<'a>,
+    vaquita: VaquitaPoolClient<'a>,
+}
+
+fn setup(e: &Env) -> Setup<'_> {
+    e.cost_estimate().budget().reset_unlimited();
+    e.mock_all_auths();
+    e.set_default_info();
+
+    let admin = Address::generate(e);
+    let blnd = e.register_stellar_asset_contract_v2(admin.clone());
+    let usdc = e.register_stellar_asset_contract_v2(admin.clone());
+    let xlm = e.register_stellar_asset_contract_v2(admin.clone());
+    let blnd = MockTokenClient::new(e, &blnd.address());
+    let usdc = MockTokenClient::new(e, &usdc.address());
+    let xlm = MockTokenClient::new(e, &xlm.address());
+
+    let blend_fixture = BlendFixture::deploy(e, &admin, &blnd.address, &usdc.address);
+    let pool = create_blend_pool(e, &blend_fixture, &admin, &usdc, &xlm, &blnd);
+
+    let vaquita = VaquitaPoolClient::new(e, &e.register(VaquitaPool, ()));
+    vaquita.initialize(&admin, &usdc.address, &pool, &Vec::from_array(e, [WEEK]));
+
+    let comet = blend_fixture.backstop_token.address.clone();
+    Setup { admin, comet, blnd, usdc, vaquita }
+}
+
+#[test]
+fn harvested_emissions_are_shared_by_depositors() {
+    let e = Env::default();
+    let s = setup(&e);
+    let alice = Address::generate(&e);
+    let bob = Address::generate(&e);
+    s.usdc.mint(&alice, &1_000_0000000);
+    s.usdc.mint(&bob, &1_000_0000000);
+
+    assert!(s.vaquita.try_harvest_emissions(&s.usdc.address).is_err());
+    s.vaquita.update_emissions_config(&s.admin, &s.blnd.address, &None);
+
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+    s.vaquita.deposit(&bob, &s.usdc.address, &String::from_str(&e, "bob"), &1_000_0000000, &WEEK);
+
+    e.jump(ONE_DAY_LEDGERS);
+    let harvested = s.vaquita.harvest_emissions(&s.usdc.address);
+    assert!(harvested > 0);
+    assert_eq!(s.blnd.balance(&s.vaquita.address), harvested);
+
+    // Alice leaves early and her share of the emissions goes to Bob
+    s.vaquita.withdraw(&alice, &s.usdc.address, &String::from_str(&e, "alice"));
+    assert_eq!(s.blnd.balance(&alice), 0);
+
+    e.jump(ONE_DAY_LEDGERS * 14);
+    s.vaquita.withdraw(&bob, &s.usdc.address, &String::from_str(&e, "bob"));
+    assert!(s.blnd.balance(&bob) >= harvested);
+    assert!(s.blnd.balance(&s.vaquita.address) <= REWARD_THRESHOLD);
+}
+
+#[test]
+fn harvested_emissions_go_to_configured_destination() {
+    let e = Env::default();
+    let s = setup(&e);
+    let alice = Address::generate(&e);
+    let treasury = Address::generate(&e);
+    s.usdc.mint(&alice, &1_000_0000000);
+
+    s.vaquita.update_emissions_config(&s.admin, &s.blnd.address, &Some(treasury.clone()));
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+
+    e.jump(ONE_DAY_LEDGERS);
+    let harvested = s.vaquita.harvest_emissions(&s.usdc.address);
+    assert!(harvested > 0);
+    assert_eq!(s.blnd.balance(&treasury), harvested);
+    assert_eq!(s.blnd.balance(&s.vaquita.address), 0);
+
+    e.jump(ONE_DAY_LEDGERS * 14);
+    s.vaquita.withdraw(&alice, &s.usdc.address, &String::from_str(&e, "alice"));
+    assert_eq!(s.blnd.balance(&alice), 0);
+}
+
+#[test]
+fn compounded_emissions_are_credited_to_round_rewards() {
+    let e = Env::default();
+    let s = setup(&e);
+    let alice = Address::generate(&e);
+    s.usdc.mint(&alice, &1_000_0000000);
+
+    s.vaquita.update_emissions_config(&s.admin, &s.blnd.address, &None);
+    s.vaquita.update_compounding_config(&s.admin, &s.usdc.address, &Some(s.comet.clone()), &0_0500000);
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+
+    // Harvesting is permissionless and needs no signature
+    e.jump(ONE_DAY_LEDGERS);
+    e.set_auths(&[]);
+    let harvested = s.vaquita.harvest_emissions(&s.usdc.address);
+    assert!(harvested > 0);
+    assert_eq!(s.blnd.balance(&s.vaquita.address), 0);
+    let reward_pool = s.vaquita.get_round(&s.usdc.address, &WEEK, &0).unwrap().reward_pool;
+    assert!(reward_pool >= harvested * 0_0500000 / SCALAR_7);
+    assert_eq!(s.vaquita.get_period_data(&s.usdc.address, &WEEK).unwrap().reward_pool, reward_pool);
+
+    // Alice collects the compounded rewards in the deposit token
+    e.mock_all_auths();
+    e.jump(ONE_DAY_LEDGERS * 14);
+    s.vaquita.withdraw(&alice, &s.usdc.address, &String::from_str(&e, "alice"));
+    assert!(s.usdc.balance(&alice) >= 1_000_0000000 + reward_pool);
+    assert_eq!(s.blnd.balance(&alice), 0);
+}
+
+#[test]
+fn compounding_respects_minimum_out() {
+    let e = Env::default();
+    let s = setup(&e);
+    let alice = Address::generate(&e);
+    s.usdc.mint(&alice, &1_000_0000000);
+
+    s.vaquita.update_emissions_config(&s.admin, &s.blnd.address, &None);
+    // Comet quotes roughly 0.1 USDC per BLND
+    s.vaquita.update_compounding_config(&s.admin, &s.usdc.address, &Some(s.comet.clone()), &1_0000000);
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+
+    // The swap can't meet the minimum, so the BLND is shared out as it is
+    e.jump(ONE_DAY_LEDGERS);
+    let harvested = s.vaquita.harvest_emissions(&s.usdc.address);
+    assert!(harvested > 0);
+    assert_eq!(s.blnd.balance(&s.vaquita.address), harvested);
+    assert_eq!(s.vaquita.get_round(&s.usdc.address, &WEEK, &0).unwrap().reward_pool, 0);
+
+    // Withdrawing harvests again under the failing minimum and still pays out
+    e.jump(ONE_DAY_LEDGERS * 14);
+    s.vaquita.withdraw(&alice, &s.usdc.address, &String::from_str(&e, "alice"));
+    assert!(s.usdc.balance(&alice) >= 1_000_0000000);
+    assert!(s.blnd.balance(&alice) > harvested);
+}
+
+#[test]
+fn deposits_and_withdrawals_keep_the_rewards_they_compound() {
+    let e = Env::default();
+    let s = setup(&e);
+    let alice = Address::generate(&e);
+    let bob = Address::generate(&e);
+    s.usdc.mint(&alice, &1_000_0000000);
+    s.usdc.mint(&bob, &1_000_0000000);
+
+    s.vaquita.update_emissions_config(&s.admin, &s.blnd.address, &None);
+    s.vaquita.update_compounding_config(&s.admin, &s.usdc.address, &Some(s.comet.clone()), &0_0500000);
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+
+    // Bob's deposit compounds Alice's emissions into the round he joins
+    e.jump(ONE_DAY_LEDGERS);
+    s.vaquita.deposit(&bob, &s.usdc.address, &String::from_str(&e, "bob"), &1_000_0000000, &WEEK);
+    let reward_pool = s.vaquita.get_round(&s.usdc.address, &WEEK, &0).unwrap().reward_pool;
+    assert!(reward_pool > 0);
+    assert_eq!(s.vaquita.get_period_data(&s.usdc.address, &WEEK).unwrap().reward_pool, reward_pool);
+
+    // Alice's early withdrawal compounds again and leaves it all to Bob
+    e.jump(ONE_DAY_LEDGERS);
+    s.vaquita.withdraw(&alice, &s.usdc.address, &String::from_str(&e, "alice"));
+    let compounded = s.vaquita.get_round(&s.usdc.address, &WEEK, &0).unwrap().reward_pool;
+    assert!(compounded > reward_pool);
+    assert_eq!(s.vaquita.get_period_data(&s.usdc.address, &WEEK).unwrap().reward_pool, compounded);
+
+    e.jump(ONE_DAY_LEDGERS * 14);
+    s.vaquita.withdraw(&bob, &s.usdc.address, &String::from_str(&e, "bob"));
+    assert!(s.usdc.balance(&bob) >= 1_000_0000000 + compounded);
+}
@@ -0,0 +1,111 @@
+#![cfg(test)]
+#![allow(clippy::inconsistent_digit_grouping)]
+use crate::test::{create_mock_pool, EnvTestUtils, VaquitaFixture, ONE_DAY_IN_SECONDS};
+use crate::{PoolAllocation, VaquitaPoolError, YieldSourceKind, MIGRATION_TIMELOCK};
+use soroban_sdk::testutils::Address as _;
+use soroban_sdk::{Address, Env, String, Vec};
+
+const WEEK: u64 = 7 * ONE_DAY_IN_SECONDS;
+
+// Blend pool status once the backstop puts it on ice
+const ON_ICE: u32 = 3;
+
+#[test]
+fn deposits_halt_and_funds_exit_an_unhealthy_pool() {
+    let e = Env::default();
+    let VaquitaFixture { admin, usdc, mock_pool, vaquita } = VaquitaFixture::deploy(&e, 1_000_000_000_000, &[WEEK]);
+    let pool = mock_pool.address.clone();
+    let alice = Address::generate(&e);
+    let bob = Address::generate(&e);
+    let keeper = Address::generate(&e);
+    let (new_pool, _) = create_mock_pool(&e, 1_000_000_000_000);
+    usdc.mint(&pool, &15_0000000);
+    usdc.mint(&alice, &100_0000000);
+    usdc.mint(&bob, &50_0000000);
+
+    let deposit = |owner: &Address, id: &str, amount: i128| {
+        vaquita.try_deposit(owner, &usdc.address, &String::from_str(&e, id), &amount, &WEEK).err()
+    };
+
+    // Disabled or capped reserves refuse deposits
+    mock_pool.set_reserve_limits(&false, &i128::MAX);
+    assert_eq!(deposit(&alice, "alice", 100_0000000), Some(Ok(VaquitaPoolError::ReserveDisabled.into())));
+    mock_pool.set_reserve_limits(&true, &50_0000000);
+    assert_eq!(deposit(&alice, "alice", 100_0000000), Some(Ok(VaquitaPoolError::SupplyCapReached.into())));
+    mock_pool.set_reserve_limits(&true, &i128::MAX);
+    assert_eq!(deposit(&alice, "alice", 100_0000000), None);
+    assert_eq!(deposit(&bob, "bob", 50_0000000), None);
+    mock_pool.set_b_rate(&1_100_000_000_000);
+    assert!(vaquita.try_emergency_exit(&keeper, &usdc.address).is_err());
+
+    // Once on ice, deposits stop and anyone can pull the funds out
+    mock_pool.set_status(&ON_ICE);
+    usdc.mint(&alice, &10_0000000);
+    assert_eq!(deposit(&alice, "late", 10_0000000), Some(Ok(VaquitaPoolError::PoolUnhealthy.into())));
+    vaquita.emergency_exit(&keeper, &usdc.address);
+    assert_eq!(mock_pool.get_positions(&vaquita.address).supply.get(0), None);
+    assert_eq!(vaquita.get_yield_source(&usdc.address), YieldSourceKind::Vault);
+    assert_eq!(usdc.balance(&vaquita.address), 165_0000000);
+    // Deposits don't silently go to the vault
+    assert_eq!(deposit(&alice, "late", 10_0000000), Some(Ok(VaquitaPoolError::PoolUnhealthy.into())));
+
+    // Positions keep the interest accrued until the exit
+    e.jump_time(2 * WEEK);
+    vaquita.withdraw(&alice, &usdc.address, &String::from_str(&e, "alice"));
+    assert_eq!(usdc.balance(&alice), 120_0000000);
+
+    // The owner moves what is left to a healthy pool
+    vaquita.propose_pool_migration(&admin, &usdc.address, &new_pool);
+    e.jump_time(MIGRATION_TIMELOCK);
+    vaquita.migrate_pool(&admin, &usdc.address, &new_pool);
+    assert_eq!(vaquita.get_yield_source(&usdc.address), YieldSourceKind::Blend);
+    vaquita.withdraw(&bob, &usdc.address, &String::from_str(&e, "bob"));
+    assert_eq!(usdc.balance(&bob), 55_0000000);
+    assert_eq!(deposit(&alice, "late", 10_0000000), None);
+}
+
+#[test]
+fn emergency_exit_empties_unhealthy_allocation_pools() {
+    let e = Env::default();
+    let VaquitaFixture { admin, usdc, mock_pool: mock_primary_pool, vaquita } = VaquitaFixture::deploy(&e, 1_000_000_000_000, &[WEEK]);
+    let alice = Address::generate(&e);
+    let bob = Address::generate(&e);
+    let keeper = Address::generate(&e);
+    let (pool_a, mock_pool_a) = create_mock_pool(&e, 1_000_000_000_000);
+    let (pool_b, _) = create_mock_pool(&e, 1_000_000_000_000);
+    usdc.mint(&pool_a, &10_0000000);
+    usdc.mint(&alice, &110_0000000);
+    usdc.mint(&bob, &100_0000000);
+
+    let allocation_a = PoolAllocation { pool: pool_a.clone(), weight: 5000, cap: 1_000_0000000 };
+    let allocation_b = PoolAllocation { pool: pool_b.clone(), weight: 5000, cap: 1_000_0000000 };
+    vaquita.set_pool_allocations(&admin, &usdc.address, &Vec::from_array(&e, [allocation_a.clone(), allocation_b.clone()]));
+    vaquita.deposit(&alice, &usdc.address, &String::from_str(&e, "alice"), &100_0000000, &WEEK);
+    vaquita.deposit(&bob, &usdc.address, &String::from_str(&e, "bob"), &100_0000000, &WEEK);
+    assert_eq!(vaquita.get_position(&String::from_str(&e, "alice")).unwrap().pool, Some(pool_a.clone()));
+
+    // Pool A goes on ice and its funds move to the configured pool
+    mock_pool_a.set_b_rate(&1_100_000_000_000);
+    mock_pool_a.set_status(&ON_ICE);
+    vaquita.emergency_exit(&keeper, &usdc.address);
+    assert_eq!(mock_pool_a.get_positions(&vaquita.address).supply.get(0), None);
+    assert_eq!(mock_primary_pool.get_positions(&vaquita.address).supply.get(0), Some(110_0000000));
+    let allocations = vaquita.get_pool_allocations(&usdc.address);
+    assert_eq!((allocations.len(), allocations.get(0).unwrap().pool), (1, pool_b.clone()));
+    let position = vaquita.get_position(&String::from_str(&e, "alice")).unwrap();
+    assert_eq!((position.pool, position.b_tokens), (None, 110_0000000));
+
+    // Deposits resume once the owner sets the allocations again, without the exited pool
+    let result = vaquita.try_deposit(&alice, &usdc.address, &String::from_str(&e, "late"), &10_0000000, &WEEK);
+    assert_eq!(result.err(), Some(Ok(VaquitaPoolError::PoolUnhealthy.into())));
+    assert!(vaquita.try_set_pool_allocations(&admin, &usdc.address, &Vec::from_array(&e, [allocation_a])).is_err());
+    vaquita.set_pool_allocations(&admin, &usdc.address, &Vec::from_array(&e, [allocation_b]));
+    vaquita.deposit(&alice, &usdc.address, &String::from_str(&e, "late"), &10_0000000, &WEEK);
+
+    e.jump_time(2 * WEEK);
+    vaquita.withdraw(&alice, &usdc.address, &String::from_str(&e, "alice"));
+    assert_eq!(usdc.balance(&alice), 110_0000000);
+    vaquita.withdraw(&bob, &usdc.address, &String::from_str(&e, "bob"));
+    assert_eq!(usdc.balance(&bob), 100_0000000);
+    assert_eq!(vaquita.get_total_b_tokens(&usdc.address), 0);
+}
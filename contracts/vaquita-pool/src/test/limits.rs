@@ -0,0 +1,70 @@
+#![cfg(test)]
+#![allow(clippy::inconsistent_digit_grouping)]
+use crate::test::{EnvTestUtils, VaquitaFixture, ONE_DAY_IN_SECONDS};
+use crate::{VaquitaPoolError, SCALAR_12};
+use soroban_sdk::testutils::Address as _;
+use soroban_sdk::{Address, Env, String};
+
+const WEEK: u64 = 7 * ONE_DAY_IN_SECONDS;
+
+#[test]
+fn deposit_respects_b_rate_and_b_token_limits() {
+    let e = Env::default();
+    let s = VaquitaFixture::deploy(&e, SCALAR_12, &[WEEK]);
+    let alice = Address::generate(&e);
+    let deposit_id = String::from_str(&e, "alice");
+    s.usdc.mint(&alice, &1_000_0000000);
+    s.mock_pool.set_b_rate(&1_100_000_000_000);
+
+    let result = s.vaquita.try_deposit_with_limits(&alice, &s.usdc.address, &deposit_id, &1_000_0000000, &WEEK, &1_050_000_000_000, &0);
+    assert_eq!(result.err(), Some(Ok(VaquitaPoolError::BRateTooHigh.into())));
+    let result = s.vaquita.try_deposit_with_limits(&alice, &s.usdc.address, &deposit_id, &1_000_0000000, &WEEK, &i128::MAX, &910_0000000);
+    assert_eq!(result.err(), Some(Ok(VaquitaPoolError::InsufficientBTokens.into())));
+    assert_eq!(s.usdc.balance(&alice), 1_000_0000000);
+
+    s.vaquita.deposit_with_limits(&alice, &s.usdc.address, &deposit_id, &1_000_0000000, &WEEK, &1_100_000_000_000, &909_0909090);
+    let position = s.vaquita.get_position(&deposit_id).unwrap();
+    assert_eq!(position.b_tokens, 909_0909090);
+    // The recorded rate is what the deposit paid per minted bToken
+    assert_eq!(position.b_rate, 1_100_000_000_111);
+}
+
+#[test]
+fn withdraw_respects_min_amount_out() {
+    let e = Env::default();
+    let s = VaquitaFixture::deploy(&e, SCALAR_12, &[WEEK]);
+    let alice = Address::generate(&e);
+    let deposit_id = String::from_str(&e, "alice");
+    s.usdc.mint(&alice, &1_000_0000000);
+
+    s.vaquita.deposit(&alice, &s.usdc.address, &deposit_id, &1_000_0000000, &WEEK);
+    s.mock_pool.set_b_rate(&950_000_000_000);
+    e.jump_time(2 * WEEK);
+
+    let result = s.vaquita.try_withdraw_with_min_amount(&alice, &s.usdc.address, &deposit_id, &1_000_0000000);
+    assert_eq!(result.err(), Some(Ok(VaquitaPoolError::InsufficientAmountOut.into())));
+    assert!(s.vaquita.get_position(&deposit_id).is_some());
+
+    s.vaquita.withdraw_with_min_amount(&alice, &s.usdc.address, &deposit_id, &950_0000000);
+    assert_eq!(s.usdc.balance(&alice), 950_0000000);
+}
+
+#[test]
+fn deposit_rejects_dust() {
+    let e = Env::default();
+    let s = VaquitaFixture::deploy(&e, SCALAR_12, &[WEEK]);
+    let alice = Address::generate(&e);
+    s.usdc.mint(&alice, &1_000_0000000);
+
+    // One stroop at a bToken rate above 1 mints nothing
+    s.mock_pool.set_b_rate(&2_000_000_000_000);
+    let result = s.vaquita.try_deposit(&alice, &s.usdc.address, &String::from_str(&e, "dust"), &1, &WEEK);
+    assert_eq!(result.err(), Some(Ok(VaquitaPoolError::ZeroBTokens.into())));
+
+    let name = String::from_str(&e, "Weekly");
+    s.vaquita.update_lock_period(&s.admin, &s.usdc.address, &WEEK, &name, &10_0000000, &i128::MAX, &i128::MAX, &10000);
+    let result = s.vaquita.try_deposit(&alice, &s.usdc.address, &String::from_str(&e, "small"), &9_9999999, &WEEK);
+    assert_eq!(result.err(), Some(Ok(VaquitaPoolError::DepositTooSmall.into())));
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &10_0000000, &WEEK);
+    assert_eq!(s.vaquita.get_position(&String::from_str(&e, "alice")).unwrap().b_tokens, 5_0000000);
+}
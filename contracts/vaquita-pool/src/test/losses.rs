@@ -0,0 +1,99 @@
+#![cfg(test)]
+#![allow(clippy::inconsistent_digit_grouping)]
+use crate::test::{EnvTestUtils, VaquitaFixture, ONE_DAY_IN_SECONDS};
+use crate::{SCALAR_12};
+use soroban_sdk::testutils::{Address as _, Events};
+use soroban_sdk::{Address, Env, IntoVal, String, Symbol};
+
+const WEEK: u64 = 7 * ONE_DAY_IN_SECONDS;
+
+fn setup(e: &Env) -> VaquitaFixture<'_> {
+    let s = VaquitaFixture::deploy(e, SCALAR_12, &[WEEK]);
+    s.usdc.mint(&s.mock_pool.address, &1_000_0000000);
+    s
+}
+
+#[test]
+fn loss_is_passed_on_without_insurance() {
+    let e = Env::default();
+    let s = setup(&e);
+    let alice = Address::generate(&e);
+    s.usdc.mint(&alice, &1_000_0000000);
+
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+    s.mock_pool.set_b_rate(&900_000_000_000);
+
+    e.jump_time(2 * WEEK);
+    s.vaquita.withdraw(&alice, &s.usdc.address, &String::from_str(&e, "alice"));
+    let loss_event = e.events().all().iter()
+        .find(|(_, topics, _)| *topics == (Symbol::new(&e, "loss_realized"), alice.clone()).into_val(&e));
+    assert!(loss_event.is_some());
+    assert_eq!(s.usdc.balance(&alice), 900_0000000);
+}
+
+#[test]
+fn loss_is_covered_by_insurance_reserve() {
+    let e = Env::default();
+    let s = setup(&e);
+    let alice = Address::generate(&e);
+    let bob = Address::generate(&e);
+    let carol = Address::generate(&e);
+    s.usdc.mint(&alice, &1_000_0000000);
+    s.usdc.mint(&bob, &1_000_0000000);
+    s.usdc.mint(&carol, &25_0000000);
+    s.vaquita.update_early_withdrawal_fee(&s.admin, &s.usdc.address, &5000);
+    s.vaquita.update_insurance_shares(&s.admin, &s.usdc.address, &5000, &4000);
+
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+    s.vaquita.deposit(&bob, &s.usdc.address, &String::from_str(&e, "bob"), &1_000_0000000, &WEEK);
+
+    // Alice leaves early: half of the 125 fee and 40% of the 125 forfeited interest
+    // are supplied to Blend for the reserve
+    s.mock_pool.set_b_rate(&1_250_000_000_000);
+    s.vaquita.withdraw(&alice, &s.usdc.address, &String::from_str(&e, "alice"));
+    assert_eq!(s.vaquita.get_insurance_b_tokens(&s.usdc.address), 90_0000000);
+    assert_eq!(s.vaquita.get_insurance_buffer(&s.usdc.address), 112_5000000);
+
+    // Anyone can top the reserve up
+    s.vaquita.top_up_insurance(&carol, &s.usdc.address, &25_0000000);
+    assert_eq!(s.vaquita.get_insurance_buffer(&s.usdc.address), 137_5000000);
+
+    // Blend loses 10% and the reserve covers what it can of Bob's 100 loss
+    s.mock_pool.set_b_rate(&900_000_000_000);
+    e.jump_time(2 * WEEK);
+    s.vaquita.withdraw(&bob, &s.usdc.address, &String::from_str(&e, "bob"));
+    assert_eq!(s.usdc.balance(&bob), 900_0000000 + 99_0000000 + 75_0000000);
+    assert_eq!(s.vaquita.get_insurance_b_tokens(&s.usdc.address), 0);
+
+    s.vaquita.withdraw_protocol_fees(&s.admin, &s.usdc.address);
+    assert_eq!(s.usdc.balance(&s.admin), 62_5000000);
+}
+
+#[test]
+fn withdrawal_waits_for_the_insurance_reserve_to_cover_its_loss() {
+    let e = Env::default();
+    let s = setup(&e);
+    let alice = Address::generate(&e);
+    let bob = Address::generate(&e);
+    let borrower = Address::generate(&e);
+    s.usdc.mint(&alice, &1_000_0000000);
+    s.usdc.mint(&bob, &100_0000000);
+
+    s.vaquita.top_up_insurance(&bob, &s.usdc.address, &100_0000000);
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+    s.mock_pool.set_b_rate(&900_000_000_000);
+
+    // Blend only has the liquidity to redeem Alice's bTokens, not the reserve's
+    e.jump_time(2 * WEEK);
+    s.mock_pool.lend(&s.usdc.address, &borrower, &1_200_0000000);
+    s.vaquita.withdraw(&alice, &s.usdc.address, &String::from_str(&e, "alice"));
+    assert_eq!(s.usdc.balance(&alice), 0);
+    assert_eq!(s.vaquita.get_withdrawal_queue(&s.usdc.address).len(), 1);
+    assert!(s.vaquita.get_position(&String::from_str(&e, "alice")).unwrap().frozen);
+    assert_eq!(s.vaquita.get_insurance_b_tokens(&s.usdc.address), 100_0000000);
+
+    s.usdc.transfer(&borrower, &s.mock_pool.address, &1_200_0000000);
+    assert_eq!(s.vaquita.process_withdrawal_queue(&s.usdc.address, &1), 1);
+    assert_eq!(s.usdc.balance(&alice), 990_0000000);
+    assert!(s.vaquita.get_position(&String::from_str(&e, "alice")).is_none());
+}
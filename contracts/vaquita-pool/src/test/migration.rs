@@ -0,0 +1,52 @@
+#![cfg(test)]
+#![allow(clippy::inconsistent_digit_grouping)]
+use crate::test::{create_mock_pool, EnvTestUtils, VaquitaFixture, ONE_DAY_IN_SECONDS};
+use crate::MIGRATION_TIMELOCK;
+use soroban_sdk::testutils::Address as _;
+use soroban_sdk::{Address, Env, String};
+
+const WEEK: u64 = 7 * ONE_DAY_IN_SECONDS;
+
+#[test]
+fn migration_preserves_accrued_interest() {
+    let e = Env::default();
+    let VaquitaFixture { admin, usdc, mock_pool: old_mock_pool, vaquita } = VaquitaFixture::deploy(&e, 1_100_000_000_000, &[WEEK]);
+    let old_pool = old_mock_pool.address.clone();
+    let alice = Address::generate(&e);
+    let bob = Address::generate(&e);
+    let (new_pool, new_mock_pool) = create_mock_pool(&e, 1_500_000_000_000);
+    usdc.mint(&old_pool, &1_000_0000000);
+    usdc.mint(&alice, &1_000_0000000);
+    usdc.mint(&bob, &500_0000000);
+
+    vaquita.deposit(&alice, &usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+    vaquita.deposit(&bob, &usdc.address, &String::from_str(&e, "bob"), &500_0000000, &WEEK);
+    old_mock_pool.set_b_rate(&1_200_000_000_000);
+    let alice_value = vaquita.preview_withdraw(&String::from_str(&e, "alice"), &0).payout;
+    let bob_value = vaquita.preview_withdraw(&String::from_str(&e, "bob"), &0).payout;
+
+    // The migration only runs once the timelock expired
+    assert!(vaquita.try_migrate_pool(&admin, &usdc.address, &new_pool).is_err());
+    vaquita.propose_pool_migration(&admin, &usdc.address, &new_pool);
+    assert!(vaquita.try_migrate_pool(&admin, &usdc.address, &new_pool).is_err());
+    e.jump_time(MIGRATION_TIMELOCK);
+    vaquita.migrate_pool(&admin, &usdc.address, &new_pool);
+    assert!(vaquita.get_pending_migration(&usdc.address).is_none());
+    assert_eq!(old_mock_pool.get_positions(&vaquita.address).supply.get(0), None);
+    let supplied = new_mock_pool.get_positions(&vaquita.address).supply.get(0).unwrap();
+    assert_eq!(supplied, vaquita.get_total_b_tokens(&usdc.address) + vaquita.get_protocol_b_tokens(&usdc.address));
+
+    // Positions keep their value in the new pool and earn its interest from now on
+    let preview = vaquita.preview_withdraw(&String::from_str(&e, "alice"), &0);
+    assert!(alice_value - preview.payout <= 1);
+    let alice_position = vaquita.get_position(&String::from_str(&e, "alice")).unwrap();
+    assert_eq!(alice_position.b_tokens, 727_2727272);
+
+    e.jump_time(2 * WEEK);
+    vaquita.withdraw(&alice, &usdc.address, &String::from_str(&e, "alice"));
+    assert!(alice_value - usdc.balance(&alice) <= 1);
+    usdc.mint(&new_pool, &100_0000000);
+    new_mock_pool.set_b_rate(&1_650_000_000_000);
+    vaquita.withdraw(&bob, &usdc.address, &String::from_str(&e, "bob"));
+    assert!(usdc.balance(&bob) >= bob_value * 11 / 10 - 1);
+}
@@ -0,0 +1,159 @@
+#![cfg(test)]
+#![allow(clippy::inconsistent_digit_grouping)]
+use crate::test::{EnvTestUtils, VaquitaFixture, ONE_DAY_IN_SECONDS};
+use crate::{LockPeriodStatus, VaquitaPool, VaquitaPoolClient, VaquitaPoolError, SCALAR_12};
+use sep_41_token::testutils::MockTokenClient;
+use soroban_sdk::testutils::{Address as _, Events};
+use soroban_sdk::{Address, Env, IntoVal, String, Symbol, Vec};
+
+const WEEK: u64 = 7 * ONE_DAY_IN_SECONDS;
+
+#[test]
+fn disabled_periods_stop_deposits_and_empty_ones_can_be_removed() {
+    let e = Env::default();
+    let VaquitaFixture { admin, usdc, vaquita, .. } = VaquitaFixture::deploy(&e, SCALAR_12, &[WEEK]);
+    let alice = Address::generate(&e);
+    usdc.mint(&admin, &100_0000000);
+    usdc.mint(&alice, &200_0000000);
+
+    vaquita.deposit(&alice, &usdc.address, &String::from_str(&e, "alice"), &100_0000000, &WEEK);
+    vaquita.add_rewards(&admin, &usdc.address, &WEEK, &10_0000000);
+    vaquita.disable_lock_period(&admin, &usdc.address, &WEEK);
+    let disabled = e.events().all().iter().find(|(_, topics, _)| {
+        *topics == (Symbol::new(&e, "lock_period_disabled"), usdc.address.clone()).into_val(&e)
+    });
+    assert!(disabled.is_some());
+
+    // No new deposits or rewards, but the open position still matures with its rewards
+    assert!(vaquita.try_deposit(&alice, &usdc.address, &String::from_str(&e, "late"), &100_0000000, &WEEK).is_err());
+    assert!(vaquita.try_preview_deposit(&usdc.address, &100_0000000, &WEEK).is_err());
+    assert!(vaquita.try_add_rewards(&admin, &usdc.address, &WEEK, &10_0000000).is_err());
+    assert!(vaquita.try_remove_lock_period(&admin, &usdc.address, &WEEK).is_err());
+    e.jump_time(2 * WEEK);
+    vaquita.withdraw(&alice, &usdc.address, &String::from_str(&e, "alice"));
+    assert_eq!(usdc.balance(&alice), 210_0000000);
+
+    vaquita.remove_lock_period(&admin, &usdc.address, &WEEK);
+    assert!(vaquita.try_deposit(&alice, &usdc.address, &String::from_str(&e, "late"), &100_0000000, &WEEK).is_err());
+    assert!(vaquita.try_disable_lock_period(&admin, &usdc.address, &WEEK).is_err());
+
+    // Added back, the period continues its round numbering and takes deposits again
+    vaquita.add_lock_period(&admin, &usdc.address, &WEEK);
+    vaquita.deposit(&alice, &usdc.address, &String::from_str(&e, "again"), &100_0000000, &WEEK);
+    assert_eq!(vaquita.get_position(&String::from_str(&e, "again")).unwrap().round, 2);
+}
+
+#[test]
+fn lock_periods_are_listed_with_their_terms() {
+    let e = Env::default();
+    let VaquitaFixture { admin, usdc, vaquita, .. } = VaquitaFixture::deploy(&e, SCALAR_12, &[WEEK]);
+    let alice = Address::generate(&e);
+    e.set_auths(&[]);
+    assert!(vaquita.try_add_lock_period(&admin, &usdc.address, &(4 * WEEK)).is_err());
+    e.mock_all_auths();
+    vaquita.add_lock_period(&admin, &usdc.address, &(4 * WEEK));
+    usdc.mint(&alice, &1_000_0000000);
+
+    let name = String::from_str(&e, "Monthly");
+    assert!(vaquita.try_update_lock_period(&admin, &usdc.address, &(4 * WEEK), &name, &10_0000000, &5_0000000, &150_0000000, &12000).is_err());
+    vaquita.update_lock_period(&admin, &usdc.address, &(4 * WEEK), &name, &10_0000000, &100_0000000, &150_0000000, &12000);
+
+    // Deposits must respect the period's limits and cap
+    let deposit = |id: &str, amount: i128| {
+        vaquita.try_deposit(&alice, &usdc.address, &String::from_str(&e, id), &amount, &(4 * WEEK))
+    };
+    assert!(deposit("small", 5_0000000).is_err());
+    assert_eq!(deposit("large", 200_0000000).err(), Some(Ok(VaquitaPoolError::DepositTooLarge.into())));
+    assert!(deposit("first", 100_0000000).is_ok());
+    assert_eq!(deposit("second", 60_0000000).err(), Some(Ok(VaquitaPoolError::PeriodCapReached.into())));
+    assert!(deposit("second", 50_0000000).is_ok());
+
+    vaquita.disable_lock_period(&admin, &usdc.address, &WEEK);
+    let lock_periods = vaquita.get_lock_periods(&usdc.address);
+    assert_eq!(lock_periods.len(), 2);
+    let week = lock_periods.get(0).unwrap();
+    assert_eq!(week.period, WEEK);
+    assert_eq!(week.config.status, LockPeriodStatus::Disabled);
+    assert_eq!(week.config.bonus_multiplier, 10000);
+    let month = lock_periods.get(1).unwrap();
+    assert_eq!(month.period, 4 * WEEK);
+    assert_eq!(month.config.name, name);
+    assert_eq!(month.config.status, LockPeriodStatus::Active);
+    assert_eq!((month.config.min_deposit, month.config.max_deposit, month.config.cap), (10_0000000, 100_0000000, 150_0000000));
+    assert_eq!(month.config.bonus_multiplier, 12000);
+    assert_eq!(month.data.total_deposits, 150_0000000);
+}
+
+#[test]
+fn periods_need_a_length_and_an_open_window_within_it() {
+    let e = Env::default();
+    let VaquitaFixture { admin, usdc, mock_pool, vaquita } = VaquitaFixture::deploy(&e, SCALAR_12, &[WEEK]);
+    let pool = mock_pool.address.clone();
+    let eurc = Address::generate(&e);
+    let fresh = VaquitaPoolClient::new(&e, &e.register(VaquitaPool, ()));
+    let result = fresh.try_initialize(&admin, &usdc.address, &pool, &Vec::from_array(&e, [WEEK, 0]));
+    assert_eq!(result.err(), Some(Ok(VaquitaPoolError::InvalidPeriod.into())));
+
+    let result = vaquita.try_add_token(&admin, &eurc, &pool, &Vec::from_array(&e, [0]));
+    assert_eq!(result.err(), Some(Ok(VaquitaPoolError::InvalidPeriod.into())));
+    // Each period is listed once
+    assert!(fresh.try_initialize(&admin, &usdc.address, &pool, &Vec::from_array(&e, [WEEK, WEEK])).is_err());
+    assert!(vaquita.try_add_token(&admin, &eurc, &pool, &Vec::from_array(&e, [WEEK, 2 * WEEK, WEEK])).is_err());
+    assert!(vaquita.try_add_lock_period(&admin, &usdc.address, &WEEK).is_err());
+    let result = vaquita.try_add_lock_period(&admin, &usdc.address, &0);
+    assert_eq!(result.err(), Some(Ok(VaquitaPoolError::InvalidPeriod.into())));
+    let result = vaquita.try_update_round_open_window(&admin, &usdc.address, &WEEK, &(WEEK + 1));
+    assert_eq!(result.err(), Some(Ok(VaquitaPoolError::InvalidOpenWindow.into())));
+    assert_eq!(vaquita.get_lock_periods(&usdc.address).len(), 1);
+}
+
+#[test]
+fn periods_with_pending_rewards_cannot_be_removed() {
+    let e = Env::default();
+    let VaquitaFixture { admin, usdc, vaquita, .. } = VaquitaFixture::deploy(&e, SCALAR_12, &[WEEK, 2 * WEEK, 3 * WEEK, 4 * WEEK]);
+    let alice = Address::generate(&e);
+    let bonus = MockTokenClient::new(&e, &e.register_stellar_asset_contract_v2(admin.clone()).address());
+    vaquita.add_reward_token(&admin, &bonus.address);
+    usdc.mint(&admin, &100_0000000);
+    bonus.mint(&admin, &10_0000000);
+    usdc.mint(&alice, &100_0000000);
+
+    // A funded round, a running stream and a bonus pot each keep their period
+    let now = e.ledger().timestamp();
+    vaquita.add_rewards(&admin, &usdc.address, &WEEK, &10_0000000);
+    vaquita.add_rewards_stream(&admin, &usdc.address, &(2 * WEEK), &10_0000000, &now, &(now + WEEK));
+    vaquita.add_rewards_in(&admin, &usdc.address, &bonus.address, &(3 * WEEK), &10_0000000);
+    for period in [WEEK, 2 * WEEK, 3 * WEEK] {
+        assert!(vaquita.try_remove_lock_period(&admin, &usdc.address, &period).is_err());
+    }
+    vaquita.remove_lock_period(&admin, &usdc.address, &(4 * WEEK));
+
+    // Once the round's depositor collected its pot the period can go
+    vaquita.deposit(&alice, &usdc.address, &String::from_str(&e, "alice"), &100_0000000, &WEEK);
+    e.jump_time(2 * WEEK);
+    vaquita.withdraw(&alice, &usdc.address, &String::from_str(&e, "alice"));
+    assert_eq!(usdc.balance(&alice), 110_0000000);
+    vaquita.remove_lock_period(&admin, &usdc.address, &WEEK);
+    assert_eq!(vaquita.get_lock_periods(&usdc.address).len(), 2);
+}
+
+#[test]
+fn periods_with_queued_withdrawals_cannot_be_removed() {
+    let e = Env::default();
+    let VaquitaFixture { admin, usdc, mock_pool, vaquita } = VaquitaFixture::deploy(&e, SCALAR_12, &[WEEK]);
+    let alice = Address::generate(&e);
+    let borrower = Address::generate(&e);
+    usdc.mint(&alice, &100_0000000);
+
+    // Alice's withdrawal waits for Blend and would credit its forfeit to the period
+    vaquita.deposit(&alice, &usdc.address, &String::from_str(&e, "alice"), &100_0000000, &WEEK);
+    mock_pool.lend(&usdc.address, &borrower, &100_0000000);
+    vaquita.withdraw(&alice, &usdc.address, &String::from_str(&e, "alice"));
+    assert_eq!(vaquita.get_withdrawal_queue(&usdc.address).len(), 1);
+    assert!(vaquita.try_remove_lock_period(&admin, &usdc.address, &WEEK).is_err());
+
+    usdc.transfer(&borrower, &mock_pool.address, &100_0000000);
+    assert_eq!(vaquita.process_withdrawal_queue(&usdc.address, &1), 1);
+    vaquita.remove_lock_period(&admin, &usdc.address, &WEEK);
+    assert!(vaquita.get_lock_periods(&usdc.address).is_empty());
+}
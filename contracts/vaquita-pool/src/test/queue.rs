@@ -0,0 +1,88 @@
+#![cfg(test)]
+#![allow(clippy::inconsistent_digit_grouping)]
+use crate::test::{EnvTestUtils, VaquitaFixture, ONE_DAY_IN_SECONDS};
+use crate::{SCALAR_12};
+use soroban_sdk::testutils::Address as _;
+use soroban_sdk::{Address, Env, String};
+
+const WEEK: u64 = 7 * ONE_DAY_IN_SECONDS;
+
+fn setup(e: &Env) -> VaquitaFixture<'_> {
+    let s = VaquitaFixture::deploy(e, SCALAR_12, &[WEEK]);
+    s.usdc.mint(&s.admin, &1_000_0000000);
+    s
+}
+
+#[test]
+fn withdrawals_wait_in_queue_until_blend_has_liquidity() {
+    let e = Env::default();
+    let s = setup(&e);
+    let alice = Address::generate(&e);
+    let bob = Address::generate(&e);
+    let borrower = Address::generate(&e);
+    s.usdc.mint(&alice, &1_000_0000000);
+    s.usdc.mint(&bob, &1_000_0000000);
+
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+    s.vaquita.deposit(&bob, &s.usdc.address, &String::from_str(&e, "bob"), &1_000_0000000, &WEEK);
+    s.vaquita.add_rewards(&s.admin, &s.usdc.address, &WEEK, &100_0000000);
+    s.mock_pool.lend(&s.usdc.address, &borrower, &1_500_0000000);
+
+    // Both withdrawals are settled and queued, their positions frozen
+    e.jump_time(2 * WEEK);
+    s.vaquita.withdraw(&alice, &s.usdc.address, &String::from_str(&e, "alice"));
+    s.vaquita.withdraw(&bob, &s.usdc.address, &String::from_str(&e, "bob"));
+    assert_eq!(s.vaquita.get_withdrawal_queue(&s.usdc.address).len(), 2);
+    assert!(s.vaquita.get_position(&String::from_str(&e, "alice")).unwrap().frozen);
+    let preview = s.vaquita.preview_withdraw(&String::from_str(&e, "alice"), &0);
+    assert_eq!((preview.reward, preview.payout), (50_0000000, 1_050_0000000));
+    assert!(s.vaquita.try_withdraw(&alice, &s.usdc.address, &String::from_str(&e, "alice")).is_err());
+    assert!(s.vaquita.try_claim_rewards(&alice, &String::from_str(&e, "alice")).is_err());
+    assert_eq!(s.vaquita.process_withdrawal_queue(&s.usdc.address, &5), 0);
+    assert_eq!(s.usdc.balance(&alice), 0);
+
+    // Liquidity comes back and the queue is paid first in, first out
+    s.usdc.transfer(&borrower, &s.mock_pool.address, &1_500_0000000);
+    assert_eq!(s.vaquita.process_withdrawal_queue(&s.usdc.address, &1), 1);
+    assert_eq!(s.usdc.balance(&alice), 1_050_0000000);
+    assert_eq!(s.usdc.balance(&bob), 0);
+    assert_eq!(s.vaquita.process_withdrawal_queue(&s.usdc.address, &5), 1);
+    assert_eq!(s.usdc.balance(&bob), 1_050_0000000);
+    assert!(s.vaquita.get_withdrawal_queue(&s.usdc.address).is_empty());
+    assert!(s.vaquita.get_position(&String::from_str(&e, "alice")).is_none());
+    assert_eq!(s.vaquita.get_total_b_tokens(&s.usdc.address), 0);
+}
+
+#[test]
+fn queued_early_withdrawals_credit_fees_once_redeemed() {
+    let e = Env::default();
+    let s = setup(&e);
+    let alice = Address::generate(&e);
+    let bob = Address::generate(&e);
+    let borrower = Address::generate(&e);
+    s.usdc.mint(&alice, &1_000_0000000);
+    s.usdc.mint(&bob, &1_000_0000000);
+    s.vaquita.update_early_withdrawal_fee(&s.admin, &s.usdc.address, &5000);
+
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+    s.vaquita.deposit(&bob, &s.usdc.address, &String::from_str(&e, "bob"), &1_000_0000000, &WEEK);
+    s.mock_pool.set_b_rate(&1_250_000_000_000);
+    s.usdc.mint(&s.mock_pool.address, &500_0000000);
+    s.mock_pool.lend(&s.usdc.address, &borrower, &2_500_0000000);
+
+    // Alice leaves early, but her 125 fee and 125 forfeited interest are still in Blend
+    s.vaquita.withdraw(&alice, &s.usdc.address, &String::from_str(&e, "alice"));
+    assert_eq!(s.vaquita.get_withdrawal_queue(&s.usdc.address).len(), 1);
+    assert_eq!(s.vaquita.get_protocol_fees(&s.usdc.address), 0);
+    assert_eq!(s.vaquita.get_round(&s.usdc.address, &WEEK, &0).unwrap().reward_pool, 0);
+
+    s.usdc.transfer(&borrower, &s.mock_pool.address, &2_500_0000000);
+    assert_eq!(s.vaquita.process_withdrawal_queue(&s.usdc.address, &1), 1);
+    assert_eq!(s.usdc.balance(&alice), 1_000_0000000);
+    assert_eq!(s.vaquita.get_protocol_fees(&s.usdc.address), 125_0000000);
+    assert_eq!(s.vaquita.get_round(&s.usdc.address, &WEEK, &0).unwrap().reward_pool, 125_0000000);
+
+    e.jump_time(2 * WEEK);
+    s.vaquita.withdraw(&bob, &s.usdc.address, &String::from_str(&e, "bob"));
+    assert_eq!(s.usdc.balance(&bob), 1_375_0000000);
+}
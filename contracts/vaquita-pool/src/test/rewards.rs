@@ -0,0 +1,435 @@
+#![cfg(test)]
+#![allow(clippy::inconsistent_digit_grouping)]
+use crate::test::{assert_approx_eq_rel, EnvTestUtils, VaquitaFixture, ONE_DAY_IN_SECONDS};
+use crate::{PartnerBoost, YieldSourceKind, MAX_MULTIPLIER, SCALAR_12};
+use sep_41_token::testutils::MockTokenClient;
+use soroban_sdk::testutils::Address as _;
+use soroban_sdk::{Address, Env, String};
+
+const WEEK: u64 = 7 * ONE_DAY_IN_SECONDS;
+
+fn setup(e: &Env) -> VaquitaFixture<'_> {
+    let s = VaquitaFixture::deploy(e, SCALAR_12, &[WEEK]);
+    s.usdc.mint(&s.admin, &1_000_000_0000000);
+    s
+}
+
+#[test]
+fn deposits_after_round_start_join_the_next_round() {
+    let e = Env::default();
+    let s = setup(&e);
+    let alice = Address::generate(&e);
+    let bob = Address::generate(&e);
+    s.usdc.mint(&alice, &1_000_0000000);
+    s.usdc.mint(&bob, &100_000_0000000);
+
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+    s.vaquita.add_rewards(&s.admin, &s.usdc.address, &WEEK, &100_0000000);
+    assert_eq!(s.vaquita.get_round(&s.usdc.address, &WEEK, &0).unwrap().reward_pool, 100_0000000);
+
+    // Bob deposits a large amount once Alice's round is running
+    e.jump_time(WEEK);
+    s.vaquita.deposit(&bob, &s.usdc.address, &String::from_str(&e, "bob"), &100_000_0000000, &WEEK);
+    let alice_position = s.vaquita.get_position(&String::from_str(&e, "alice")).unwrap();
+    let bob_position = s.vaquita.get_position(&String::from_str(&e, "bob")).unwrap();
+    assert_eq!(alice_position.round, 0);
+    assert_eq!(bob_position.round, 1);
+    assert_eq!(bob_position.finalization_time, alice_position.finalization_time + WEEK);
+
+    e.jump_time(WEEK);
+    s.vaquita.withdraw(&alice, &s.usdc.address, &String::from_str(&e, "alice"));
+    assert_eq!(s.usdc.balance(&alice), 1_100_0000000);
+
+    // Bob's round had no pot of its own
+    e.jump_time(WEEK);
+    s.vaquita.withdraw(&bob, &s.usdc.address, &String::from_str(&e, "bob"));
+    assert_eq!(s.usdc.balance(&bob), 100_000_0000000);
+    assert_eq!(s.vaquita.get_period_data(&s.usdc.address, &WEEK).unwrap().total_deposits, 0);
+}
+
+#[test]
+fn early_withdrawal_forfeits_to_remaining_depositors_only() {
+    let e = Env::default();
+    let s = setup(&e);
+    let alice = Address::generate(&e);
+    let bob = Address::generate(&e);
+    let carol = Address::generate(&e);
+    s.usdc.mint(&alice, &1_000_0000000);
+    s.usdc.mint(&bob, &1_000_0000000);
+    s.usdc.mint(&carol, &1_000_0000000);
+
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+    s.vaquita.deposit(&bob, &s.usdc.address, &String::from_str(&e, "bob"), &1_000_0000000, &WEEK);
+    s.vaquita.add_rewards(&s.admin, &s.usdc.address, &WEEK, &100_0000000);
+
+    // 10% yield, then Bob leaves halfway through the round and forfeits his interest and rewards
+    e.jump_time(WEEK + WEEK / 2);
+    s.mock_pool.set_b_rate(&1_100_000_000_000);
+    s.usdc.mint(&s.mock_pool.address, &200_0000000);
+    s.vaquita.withdraw(&bob, &s.usdc.address, &String::from_str(&e, "bob"));
+    assert_eq!(s.usdc.balance(&bob), 1_000_0000000);
+
+    // Carol joins after the forfeiture and must not share in it
+    s.vaquita.deposit(&carol, &s.usdc.address, &String::from_str(&e, "carol"), &1_000_0000000, &WEEK);
+
+    e.jump_time(WEEK / 2);
+    s.vaquita.withdraw(&alice, &s.usdc.address, &String::from_str(&e, "alice"));
+    // principal + own interest + Bob's interest + all of the rewards
+    assert_eq!(s.usdc.balance(&alice), 1_300_0000000);
+
+    e.jump_time(WEEK);
+    s.vaquita.withdraw(&carol, &s.usdc.address, &String::from_str(&e, "carol"));
+    assert_approx_eq_rel(s.usdc.balance(&carol), 1_000_0000000, 1);
+}
+
+#[test]
+fn rewards_of_an_empty_round_roll_over() {
+    let e = Env::default();
+    let s = setup(&e);
+    let alice = Address::generate(&e);
+    s.usdc.mint(&alice, &1_000_0000000);
+
+    s.vaquita.add_rewards(&s.admin, &s.usdc.address, &WEEK, &100_0000000);
+    let round = s.vaquita.get_round(&s.usdc.address, &WEEK, &0).unwrap();
+    assert_eq!(round.reward_pool, 100_0000000);
+    assert_eq!(round.reward_per_share, 0);
+
+    // Nobody joined round 0, so its pot moves to the round Alice joins
+    e.jump_time(WEEK);
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+    s.vaquita.rollover_rewards(&s.usdc.address, &WEEK, &0);
+    assert_eq!(s.vaquita.get_round(&s.usdc.address, &WEEK, &0).unwrap().reward_pool, 0);
+    assert_eq!(s.vaquita.get_round(&s.usdc.address, &WEEK, &1).unwrap().reward_pool, 100_0000000);
+
+    e.jump_time(2 * WEEK);
+    s.vaquita.withdraw(&alice, &s.usdc.address, &String::from_str(&e, "alice"));
+    assert_eq!(s.usdc.balance(&alice), 1_100_0000000);
+}
+
+#[test]
+fn deposits_only_accepted_during_open_window() {
+    let e = Env::default();
+    let s = setup(&e);
+    let alice = Address::generate(&e);
+    s.usdc.mint(&alice, &1_000_0000000);
+
+    s.vaquita.update_round_open_window(&s.admin, &s.usdc.address, &WEEK, &ONE_DAY_IN_SECONDS);
+    let result = s.vaquita.try_deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+    assert!(result.is_err());
+
+    e.jump_time(WEEK - ONE_DAY_IN_SECONDS);
+    assert_eq!(s.vaquita.get_current_round(&s.usdc.address, &WEEK), 0);
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+    let round = s.vaquita.get_round(&s.usdc.address, &WEEK, &0).unwrap();
+    assert_eq!(round.total_deposits, 1_000_0000000);
+    assert_eq!(
+        s.vaquita.get_position(&String::from_str(&e, "alice")).unwrap().finalization_time,
+        round.end_time
+    );
+}
+
+#[test]
+fn reward_stream_vests_linearly_across_rounds() {
+    let e = Env::default();
+    let s = setup(&e);
+    let alice = Address::generate(&e);
+    let carol = Address::generate(&e);
+    s.usdc.mint(&alice, &1_000_0000000);
+    s.usdc.mint(&carol, &1_000_0000000);
+
+    // Stream 100 over rounds 0 and 1
+    let now = e.ledger().timestamp();
+    s.vaquita.add_rewards_stream(&s.admin, &s.usdc.address, &WEEK, &100_0000000, &(now + WEEK), &(now + 3 * WEEK));
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+    assert_eq!(s.vaquita.get_vested_stream_rewards(&s.usdc.address, &WEEK), 0);
+    assert_eq!(s.vaquita.get_pending_stream_rewards(&s.usdc.address, &WEEK), 100_0000000);
+
+    e.jump_time(WEEK + WEEK / 2);
+    assert_eq!(s.vaquita.get_vested_stream_rewards(&s.usdc.address, &WEEK), 25_0000000);
+    assert_eq!(s.vaquita.get_pending_stream_rewards(&s.usdc.address, &WEEK), 75_0000000);
+    s.vaquita.deposit(&carol, &s.usdc.address, &String::from_str(&e, "carol"), &1_000_0000000, &WEEK);
+
+    // Alice only collects what vested while her round was running
+    e.jump_time(WEEK / 2);
+    s.vaquita.withdraw(&alice, &s.usdc.address, &String::from_str(&e, "alice"));
+    assert_eq!(s.usdc.balance(&alice), 1_050_0000000);
+    assert_eq!(s.vaquita.get_period_data(&s.usdc.address, &WEEK).unwrap().reward_pool, 0);
+
+    e.jump_time(WEEK);
+    s.vaquita.withdraw(&carol, &s.usdc.address, &String::from_str(&e, "carol"));
+    assert_eq!(s.usdc.balance(&carol), 1_050_0000000);
+    assert_eq!(s.vaquita.get_period_data(&s.usdc.address, &WEEK).unwrap().reward_pool, 0);
+    assert_eq!(s.vaquita.get_pending_stream_rewards(&s.usdc.address, &WEEK), 0);
+    assert_eq!(s.vaquita.get_reward_streams(&s.usdc.address, &WEEK).len(), 0);
+}
+
+#[test]
+fn leaving_mid_stream_forfeits_the_vested_share() {
+    let e = Env::default();
+    let s = setup(&e);
+    let alice = Address::generate(&e);
+    let bob = Address::generate(&e);
+    s.usdc.mint(&alice, &1_000_0000000);
+    s.usdc.mint(&bob, &1_000_0000000);
+
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+    s.vaquita.deposit(&bob, &s.usdc.address, &String::from_str(&e, "bob"), &1_000_0000000, &WEEK);
+    let start = e.ledger().timestamp() + WEEK;
+    s.vaquita.add_rewards_stream(&s.admin, &s.usdc.address, &WEEK, &100_0000000, &start, &(start + WEEK));
+
+    // Bob leaves halfway through the stream and his vested share goes to Alice
+    e.jump_time(WEEK / 2 + WEEK);
+    s.vaquita.withdraw(&bob, &s.usdc.address, &String::from_str(&e, "bob"));
+    assert_eq!(s.usdc.balance(&bob), 1_000_0000000);
+
+    e.jump_time(WEEK / 2);
+    s.vaquita.withdraw(&alice, &s.usdc.address, &String::from_str(&e, "alice"));
+    assert_eq!(s.usdc.balance(&alice), 1_100_0000000);
+}
+
+#[test]
+fn bonus_token_rewards_are_paid_on_withdraw() {
+    let e = Env::default();
+    let s = setup(&e);
+    let sponsor = Address::generate(&e);
+    let alice = Address::generate(&e);
+    let bob = Address::generate(&e);
+    let blnd = e.register_stellar_asset_contract_v2(s.admin.clone());
+    let blnd = MockTokenClient::new(&e, &blnd.address());
+    blnd.mint(&sponsor, &1_000_0000000);
+    s.usdc.mint(&alice, &1_000_0000000);
+    s.usdc.mint(&bob, &1_000_0000000);
+
+    assert!(s.vaquita.try_add_rewards_in(&sponsor, &s.usdc.address, &blnd.address, &WEEK, &100_0000000).is_err());
+    s.vaquita.add_reward_token(&s.admin, &blnd.address);
+    assert_eq!(s.vaquita.get_reward_tokens().len(), 1);
+    s.vaquita.add_rewards_in(&sponsor, &s.usdc.address, &blnd.address, &WEEK, &100_0000000);
+    assert_eq!(s.vaquita.get_round_rewards(&s.usdc.address, &WEEK, &0, &blnd.address).unwrap().reward_pool, 100_0000000);
+
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+    s.vaquita.deposit(&bob, &s.usdc.address, &String::from_str(&e, "bob"), &1_000_0000000, &WEEK);
+
+    // Bob leaves early and forfeits his share of the sponsored pot
+    e.jump_time(WEEK + WEEK / 2);
+    s.vaquita.withdraw(&bob, &s.usdc.address, &String::from_str(&e, "bob"));
+    assert_eq!(blnd.balance(&bob), 0);
+
+    e.jump_time(WEEK / 2);
+    s.vaquita.withdraw(&alice, &s.usdc.address, &String::from_str(&e, "alice"));
+    assert_eq!(s.usdc.balance(&alice), 1_000_0000000);
+    assert_eq!(blnd.balance(&alice), 100_0000000);
+    assert_eq!(s.vaquita.get_round_rewards(&s.usdc.address, &WEEK, &0, &blnd.address).unwrap().reward_pool, 0);
+}
+
+#[test]
+fn claim_rewards_keeps_the_position_open() {
+    let e = Env::default();
+    let s = setup(&e);
+    let alice = Address::generate(&e);
+    let blnd = e.register_stellar_asset_contract_v2(s.admin.clone());
+    let blnd = MockTokenClient::new(&e, &blnd.address());
+    blnd.mint(&s.admin, &1_000_0000000);
+    s.usdc.mint(&alice, &1_000_0000000);
+
+    s.vaquita.add_reward_token(&s.admin, &blnd.address);
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+    s.vaquita.add_rewards(&s.admin, &s.usdc.address, &WEEK, &10_0000000);
+    s.vaquita.add_rewards_in(&s.admin, &s.usdc.address, &blnd.address, &WEEK, &50_0000000);
+
+    e.jump_time(WEEK);
+    assert!(s.vaquita.try_claim_rewards(&alice, &String::from_str(&e, "alice")).is_err());
+
+    e.jump_time(WEEK);
+    s.vaquita.claim_rewards(&alice, &String::from_str(&e, "alice"));
+    assert_eq!(s.usdc.balance(&alice), 10_0000000);
+    assert_eq!(blnd.balance(&alice), 50_0000000);
+    assert!(s.vaquita.get_position(&String::from_str(&e, "alice")).is_some());
+
+    // Claiming again pays nothing and withdrawing returns the principal only
+    s.vaquita.claim_rewards(&alice, &String::from_str(&e, "alice"));
+    s.vaquita.withdraw(&alice, &s.usdc.address, &String::from_str(&e, "alice"));
+    assert_eq!(s.usdc.balance(&alice), 1_010_0000000);
+    assert_eq!(blnd.balance(&alice), 50_0000000);
+}
+
+#[test]
+fn positions_redeem_the_b_tokens_minted_for_them() {
+    let e = Env::default();
+    let s = setup(&e);
+    let alice = Address::generate(&e);
+    let bob = Address::generate(&e);
+    s.usdc.mint(&alice, &1_000_0000000);
+    s.usdc.mint(&bob, &333_3333333);
+    s.usdc.mint(&s.mock_pool.address, &1_000_0000000);
+    s.mock_pool.set_b_rate(&1_100_000_000_000);
+
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+    s.vaquita.deposit(&bob, &s.usdc.address, &String::from_str(&e, "bob"), &333_3333333, &WEEK);
+    let alice_position = s.vaquita.get_position(&String::from_str(&e, "alice")).unwrap();
+    let bob_position = s.vaquita.get_position(&String::from_str(&e, "bob")).unwrap();
+    assert_eq!(alice_position.b_tokens, 909_0909090);
+    assert_eq!(bob_position.b_tokens, 303_0303030);
+    let supplied = |s: &VaquitaFixture| s.mock_pool.get_positions(&s.vaquita.address).supply.get(0).unwrap_or(0);
+    assert_eq!(supplied(&s), s.vaquita.get_total_b_tokens(&s.usdc.address));
+
+    // Blend balance stays equal to the positions plus the protocol-owned dust
+    s.mock_pool.set_b_rate(&1_234_567_890_123);
+    e.jump_time(2 * WEEK);
+    s.vaquita.withdraw(&alice, &s.usdc.address, &String::from_str(&e, "alice"));
+    assert_eq!(s.usdc.balance(&alice), 909_0909090 * 1_234_567_890_123 / SCALAR_12);
+    assert_eq!(s.vaquita.get_total_b_tokens(&s.usdc.address), bob_position.b_tokens);
+    assert_eq!(supplied(&s), s.vaquita.get_total_b_tokens(&s.usdc.address) + s.vaquita.get_protocol_b_tokens(&s.usdc.address));
+
+    s.vaquita.withdraw(&bob, &s.usdc.address, &String::from_str(&e, "bob"));
+    assert_eq!(s.vaquita.get_total_b_tokens(&s.usdc.address), 0);
+    assert_eq!(supplied(&s), s.vaquita.get_protocol_b_tokens(&s.usdc.address));
+}
+
+#[test]
+fn previews_match_deposits_and_withdrawals() {
+    let e = Env::default();
+    let s = setup(&e);
+    let alice = Address::generate(&e);
+    let bob = Address::generate(&e);
+    s.usdc.mint(&alice, &1_000_0000000);
+    s.usdc.mint(&bob, &1_000_0000000);
+    s.usdc.mint(&s.mock_pool.address, &1_000_0000000);
+    s.vaquita.update_early_withdrawal_fee(&s.admin, &s.usdc.address, &5000);
+
+    let preview = s.vaquita.preview_deposit(&s.usdc.address, &1_000_0000000, &WEEK);
+    assert_eq!((preview.b_tokens, preview.round, preview.maturity), (1_000_0000000, 0, e.ledger().timestamp() + 2 * WEEK));
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+    s.vaquita.deposit(&bob, &s.usdc.address, &String::from_str(&e, "bob"), &1_000_0000000, &WEEK);
+    s.vaquita.add_rewards(&s.admin, &s.usdc.address, &WEEK, &100_0000000);
+
+    e.jump_time(WEEK + 1);
+    s.mock_pool.set_b_rate(&1_100_000_000_000);
+    let preview = s.vaquita.preview_deposit(&s.usdc.address, &1_000_0000000, &WEEK);
+    assert_eq!((preview.b_tokens, preview.round), (909_0909090, 1));
+
+    // Alice leaving early gives up half her interest and her reward share
+    let preview = s.vaquita.preview_withdraw(&String::from_str(&e, "alice"), &e.ledger().timestamp());
+    assert_eq!(preview.principal, 1_000_0000000);
+    assert_eq!(preview.interest, 100_0000000);
+    assert_eq!(preview.early_fee, 50_0000000);
+    assert_eq!(preview.forfeited_interest, 50_0000000);
+    assert_eq!(preview.reward, 0);
+    assert_eq!(preview.payout, 1_000_0000000);
+    s.vaquita.withdraw(&alice, &s.usdc.address, &String::from_str(&e, "alice"));
+    assert_eq!(s.usdc.balance(&alice), preview.payout);
+
+    // Bob collects the pot and everything Alice forfeited at maturity
+    let maturity = s.vaquita.get_position(&String::from_str(&e, "bob")).unwrap().finalization_time;
+    let preview = s.vaquita.preview_withdraw(&String::from_str(&e, "bob"), &maturity);
+    assert_eq!(preview.reward, 150_0000000);
+    assert_eq!(preview.payout, 1_250_0000000);
+    e.jump_time(WEEK);
+    s.vaquita.withdraw(&bob, &s.usdc.address, &String::from_str(&e, "bob"));
+    assert_eq!(s.usdc.balance(&bob), preview.payout);
+}
+
+#[test]
+fn previews_accrue_streams_and_bonus_rewards_until_maturity() {
+    let e = Env::default();
+    let s = setup(&e);
+    let alice = Address::generate(&e);
+    let bonus = e.register_stellar_asset_contract_v2(s.admin.clone());
+    let bonus = MockTokenClient::new(&e, &bonus.address());
+    bonus.mint(&s.admin, &100_0000000);
+    s.usdc.mint(&alice, &1_000_0000000);
+    s.vaquita.add_reward_token(&s.admin, &bonus.address);
+
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+    s.vaquita.add_rewards_in(&s.admin, &s.usdc.address, &bonus.address, &WEEK, &100_0000000);
+    let start = e.ledger().timestamp() + WEEK;
+    s.vaquita.add_rewards_stream(&s.admin, &s.usdc.address, &WEEK, &100_0000000, &start, &(start + WEEK));
+
+    // Halfway through the stream the preview at maturity counts all of it
+    e.jump_time(WEEK + WEEK / 2);
+    let maturity = s.vaquita.get_position(&String::from_str(&e, "alice")).unwrap().finalization_time;
+    let preview = s.vaquita.preview_withdraw(&String::from_str(&e, "alice"), &maturity);
+    assert_eq!(preview.reward, 100_0000000);
+    assert_eq!(preview.bonus_rewards.get(bonus.address.clone()), Some(100_0000000));
+    assert_eq!(preview.payout, 1_100_0000000);
+    assert_eq!(s.vaquita.get_round(&s.usdc.address, &WEEK, &0).unwrap().reward_pool, 0);
+    let early = s.vaquita.preview_withdraw(&String::from_str(&e, "alice"), &e.ledger().timestamp());
+    assert_eq!((early.reward, early.payout), (0, 1_000_0000000));
+    assert_eq!(early.bonus_rewards.get(bonus.address.clone()), Some(0));
+
+    e.jump_time(WEEK / 2);
+    s.vaquita.withdraw(&alice, &s.usdc.address, &String::from_str(&e, "alice"));
+    assert_eq!(s.usdc.balance(&alice), preview.payout);
+    assert_eq!(bonus.balance(&alice), 100_0000000);
+}
+
+#[test]
+fn vault_source_keeps_deposits_in_the_contract() {
+    let e = Env::default();
+    let s = setup(&e);
+    let alice = Address::generate(&e);
+    s.usdc.mint(&alice, &1_000_0000000);
+
+    s.vaquita.update_yield_source(&s.admin, &s.usdc.address, &YieldSourceKind::Vault);
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &1_000_0000000, &WEEK);
+    s.vaquita.add_rewards(&s.admin, &s.usdc.address, &WEEK, &100_0000000);
+    assert_eq!(s.usdc.balance(&s.vaquita.address), 1_100_0000000);
+    assert_eq!(s.usdc.balance(&s.mock_pool.address), 0);
+    assert!(s.vaquita.try_update_yield_source(&s.admin, &s.usdc.address, &YieldSourceKind::Blend).is_err());
+
+    e.jump_time(2 * WEEK);
+    s.vaquita.withdraw(&alice, &s.usdc.address, &String::from_str(&e, "alice"));
+    assert_eq!(s.usdc.balance(&alice), 1_100_0000000);
+    s.vaquita.update_yield_source(&s.admin, &s.usdc.address, &YieldSourceKind::Blend);
+}
+
+#[test]
+fn rewards_are_split_by_multiplier_weighted_shares() {
+    let e = Env::default();
+    let s = setup(&e);
+    let alice = Address::generate(&e);
+    let bob = Address::generate(&e);
+    let carol = Address::generate(&e);
+    let partner = MockTokenClient::new(&e, &e.register_stellar_asset_contract_v2(s.admin.clone()).address());
+    s.usdc.mint(&alice, &100_0000000);
+    s.usdc.mint(&bob, &100_0000000);
+    s.usdc.mint(&carol, &100_0000000);
+    partner.mint(&carol, &10_0000000);
+
+    // Alice has a loyalty tier, Carol holds the partner token
+    s.vaquita.update_user_multiplier(&s.admin, &alice, &15000);
+    assert!(s.vaquita.try_update_user_multiplier(&s.admin, &bob, &0).is_err());
+    s.vaquita.update_partner_boost(
+        &s.admin,
+        &Some(PartnerBoost { token: partner.address.clone(), min_balance: 10_0000000, multiplier: 20000 }),
+    );
+    assert_eq!(s.vaquita.get_reward_multiplier(&s.usdc.address, &WEEK, &bob), 10000);
+    assert_eq!(s.vaquita.get_reward_multiplier(&s.usdc.address, &WEEK, &carol), 20000);
+
+    s.vaquita.deposit(&alice, &s.usdc.address, &String::from_str(&e, "alice"), &100_0000000, &WEEK);
+    s.vaquita.deposit(&bob, &s.usdc.address, &String::from_str(&e, "bob"), &100_0000000, &WEEK);
+    s.vaquita.deposit(&carol, &s.usdc.address, &String::from_str(&e, "carol"), &100_0000000, &WEEK);
+    s.vaquita.add_rewards(&s.admin, &s.usdc.address, &WEEK, &90_0000000);
+
+    // The multiplier is fixed at deposit time
+    s.vaquita.update_partner_boost(&s.admin, &None);
+    assert_eq!(s.vaquita.get_position(&String::from_str(&e, "alice")).unwrap().multiplier, 15000);
+    assert_eq!(s.vaquita.get_position(&String::from_str(&e, "carol")).unwrap().multiplier, 20000);
+    assert_eq!(s.vaquita.get_round(&s.usdc.address, &WEEK, &0).unwrap().total_shares, 450_0000000);
+
+    // Stacked multipliers never exceed MAX_MULTIPLIER
+    s.vaquita.update_user_multiplier(&s.admin, &carol, &MAX_MULTIPLIER);
+    s.vaquita.update_partner_boost(
+        &s.admin,
+        &Some(PartnerBoost { token: partner.address.clone(), min_balance: 10_0000000, multiplier: MAX_MULTIPLIER }),
+    );
+    assert_eq!(s.vaquita.get_reward_multiplier(&s.usdc.address, &WEEK, &carol), MAX_MULTIPLIER);
+    s.vaquita.update_partner_boost(&s.admin, &None);
+
+    e.jump_time(2 * WEEK);
+    for (user, id) in [(&alice, "alice"), (&bob, "bob"), (&carol, "carol")] {
+        s.vaquita.withdraw(user, &s.usdc.address, &String::from_str(&e, id));
+    }
+    assert_eq!(s.usdc.balance(&alice), 130_0000000);
+    assert_eq!(s.usdc.balance(&bob), 120_0000000);
+    assert_eq!(s.usdc.balance(&carol), 140_0000000);
+}
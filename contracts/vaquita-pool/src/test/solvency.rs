@@ -0,0 +1,101 @@
+#![cfg(test)]
+#![allow(clippy::inconsistent_digit_grouping)]
+extern crate std;
+use crate::test::{EnvTestUtils, VaquitaFixture, ONE_DAY_IN_SECONDS};
+use crate::{SCALAR_12};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use soroban_sdk::testutils::Address as _;
+use soroban_sdk::{Address, Env, String};
+
+const WEEK: u64 = 7 * ONE_DAY_IN_SECONDS;
+const MONTH: u64 = 30 * ONE_DAY_IN_SECONDS;
+
+fn setup(e: &Env) -> VaquitaFixture<'_> {
+    let s = VaquitaFixture::deploy(e, SCALAR_12, &[WEEK, MONTH]);
+    // Liquidity for the interest the mock pool pays out
+    s.usdc.mint(&s.mock_pool.address, &1_000_000_000_0000000);
+    s.vaquita.update_early_withdrawal_fee(&s.admin, &s.usdc.address, &1500);
+    s.vaquita.update_insurance_shares(&s.admin, &s.usdc.address, &5000, &2000);
+    s.usdc.mint(&s.admin, &1_000_000_000_0000000);
+    s
+}
+
+/// Everything the contract owes besides the principal of the open positions must be
+/// covered by its idle balance plus the protocol-owned bTokens, and its Blend balance
+/// must match the bTokens it accounts for.
+fn assert_solvent(e: &Env, s: &VaquitaFixture, b_rate: i128) {
+    let supplied = s.mock_pool.get_positions(&s.vaquita.address).supply.get(0).unwrap_or(0);
+    let protocol_b_tokens = s.vaquita.get_protocol_b_tokens(&s.usdc.address);
+    let insurance_b_tokens = s.vaquita.get_insurance_b_tokens(&s.usdc.address);
+    assert_eq!(supplied, s.vaquita.get_total_b_tokens(&s.usdc.address) + protocol_b_tokens + insurance_b_tokens);
+
+    let mut owed = e.as_contract(&s.vaquita.address, || {
+        e.storage().instance().get::<_, i128>(&crate::DataKey::ProtocolFees(s.usdc.address.clone())).unwrap()
+    });
+    for period in [WEEK, MONTH] {
+        owed += s.vaquita.get_period_data(&s.usdc.address, &period).map_or(0, |data| data.reward_pool);
+        owed += s.vaquita.get_pending_stream_rewards(&s.usdc.address, &period);
+    }
+    let holdings = s.usdc.balance(&s.vaquita.address) + protocol_b_tokens * b_rate / SCALAR_12;
+    assert!(holdings >= owed, "owes {} but holds {}", owed, holdings);
+}
+
+fn run(seed: u64) {
+    let e = Env::default();
+    let s = setup(&e);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut b_rate = SCALAR_12 + rng.gen_range(0..SCALAR_12 / 10);
+    s.mock_pool.set_b_rate(&b_rate);
+
+    let mut open: std::vec::Vec<(Address, String)> = std::vec::Vec::new();
+    let mut next_id = 0u32;
+    for _ in 0..40 {
+        match rng.gen_range(0..6) {
+            0 | 1 => {
+                let user = Address::generate(&e);
+                let amount = rng.gen_range(1..100_000_0000000i128);
+                let period = if rng.gen_bool(0.5) { WEEK } else { MONTH };
+                s.usdc.mint(&user, &amount);
+                let deposit_id = String::from_str(&e, &std::format!("deposit-{}", next_id));
+                next_id += 1;
+                if s.vaquita.try_deposit(&user, &s.usdc.address, &deposit_id, &amount, &period).is_ok() {
+                    open.push((user, deposit_id));
+                }
+            }
+            2 => {
+                let period = if rng.gen_bool(0.5) { WEEK } else { MONTH };
+                s.vaquita.add_rewards(&s.admin, &s.usdc.address, &period, &rng.gen_range(1..10_000_0000000i128));
+            }
+            3 if !open.is_empty() => {
+                let (user, deposit_id) = open.swap_remove(rng.gen_range(0..open.len()));
+                s.vaquita.withdraw(&user, &s.usdc.address, &deposit_id);
+            }
+            4 if !open.is_empty() => {
+                let (user, deposit_id) = &open[rng.gen_range(0..open.len())];
+                let _ = s.vaquita.try_claim_rewards(user, deposit_id);
+            }
+            _ => {
+                // Mostly interest, sometimes a socialized loss
+                b_rate += rng.gen_range(-SCALAR_12 / 200..SCALAR_12 / 100);
+                s.mock_pool.set_b_rate(&b_rate);
+                e.jump_time(rng.gen_range(0..2 * WEEK));
+            }
+        }
+        assert_solvent(&e, &s, b_rate);
+    }
+
+    // Every position can still leave, matured or not
+    for (user, deposit_id) in open.iter() {
+        s.vaquita.withdraw(user, &s.usdc.address, deposit_id);
+        assert_solvent(&e, &s, b_rate);
+    }
+    assert_eq!(s.vaquita.get_total_b_tokens(&s.usdc.address), 0);
+    s.vaquita.withdraw_protocol_fees(&s.admin, &s.usdc.address);
+}
+
+#[test]
+fn contract_never_owes_more_than_it_holds() {
+    for seed in 0..8 {
+        run(seed);
+    }
+}
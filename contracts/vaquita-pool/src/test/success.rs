@@ -1,11 +1,12 @@
 #![cfg(test)]
+#![allow(clippy::inconsistent_digit_grouping)]
 use crate::{VaquitaPoolClient, VaquitaPool};
 use crate::test::{create_blend_pool, BlendFixture, EnvTestUtils, assert_approx_eq_rel};
 use crate::BlendPoolClient;
 use crate::Request;
 use sep_41_token::testutils::MockTokenClient;
-use soroban_sdk::testutils::{Address as _, AuthorizedFunction, AuthorizedInvocation, MockAuth, MockAuthInvoke, Events};
-use soroban_sdk::{vec, Address, Bytes, Env, IntoVal, Symbol, Vec, Val, symbol_short, String, FromVal, log};
+use soroban_sdk::testutils::Address as _;
+use soroban_sdk::{vec, Address, Env, Vec, String};
 use crate::test::std::println;
 
 #[test]
@@ -68,10 +69,10 @@ fn success() {
     vaquita_client.initialize(&admin, &usdc.address(), &pool, &lock_periods);
     println!("Vaquita pool initialized");
 
-    vaquita_client.deposit(&alice, &String::from_str(&e, "TEST"), &200_000_0000000, &604800);
+    vaquita_client.deposit(&alice, &usdc.address(), &String::from_str(&e, "TEST"), &200_000_0000000, &604800);
     println!("Vaquita pool deposited");
     
-    vaquita_client.withdraw(&alice, &String::from_str(&e, "TEST"));
+    vaquita_client.withdraw(&alice, &usdc.address(), &String::from_str(&e, "TEST"));
     println!("Vaquita pool withdrew");
     assert_approx_eq_rel(usdc_client.balance(&alice), 200_000_0000000, 1);
 }
\ No newline at end of file
@@ -0,0 +1,88 @@
+#![cfg(test)]
+use crate::test::{create_mock_pool, EnvTestUtils, VaquitaFixture, ONE_DAY_IN_SECONDS};
+use crate::SCALAR_12;
+use sep_41_token::testutils::MockTokenClient;
+use soroban_sdk::testutils::Address as _;
+use soroban_sdk::{Address, Env, String, Vec};
+
+const WEEK: u64 = 7 * ONE_DAY_IN_SECONDS;
+
+#[test]
+fn each_token_keeps_its_own_pool_periods_and_fees() {
+    let e = Env::default();
+    let VaquitaFixture { admin, usdc, mock_pool: mock_usdc_pool, vaquita } = VaquitaFixture::deploy(&e, SCALAR_12, &[WEEK]);
+    let usdc_pool = mock_usdc_pool.address.clone();
+    let alice = Address::generate(&e);
+    let bob = Address::generate(&e);
+    let eurc = MockTokenClient::new(&e, &e.register_stellar_asset_contract_v2(admin.clone()).address());
+    let (eurc_pool, _) = create_mock_pool(&e, SCALAR_12);
+    usdc.mint(&usdc_pool, &10_0000000);
+    usdc.mint(&alice, &100_0000000);
+    eurc.mint(&bob, &200_0000000);
+
+    vaquita.add_token(&admin, &eurc.address, &eurc_pool, &Vec::from_array(&e, [2 * WEEK]));
+    assert!(vaquita.try_add_token(&admin, &eurc.address, &eurc_pool, &Vec::new(&e)).is_err());
+    assert!(vaquita.try_add_reward_token(&admin, &eurc.address).is_err());
+    assert_eq!(vaquita.get_tokens(), Vec::from_array(&e, [usdc.address.clone(), eurc.address.clone()]));
+
+    // Lock periods are per token and each token is supplied to its own pool
+    assert!(vaquita.try_deposit(&bob, &eurc.address, &String::from_str(&e, "bob"), &200_0000000, &WEEK).is_err());
+    vaquita.deposit(&alice, &usdc.address, &String::from_str(&e, "alice"), &100_0000000, &WEEK);
+    vaquita.deposit(&bob, &eurc.address, &String::from_str(&e, "bob"), &200_0000000, &(2 * WEEK));
+    assert_eq!(usdc.balance(&usdc_pool), 110_0000000);
+    assert_eq!(eurc.balance(&eurc_pool), 200_0000000);
+    assert_eq!(vaquita.get_period_data(&usdc.address, &WEEK).unwrap().total_deposits, 100_0000000);
+    assert_eq!(vaquita.get_period_data(&eurc.address, &(2 * WEEK)).unwrap().total_deposits, 200_0000000);
+    assert!(vaquita.get_period_data(&eurc.address, &WEEK).is_none());
+
+    // The early withdrawal fee only applies to the token it was set for
+    e.set_auths(&[]);
+    assert!(vaquita.try_update_early_withdrawal_fee(&admin, &usdc.address, &5000).is_err());
+    e.mock_all_auths();
+    vaquita.update_early_withdrawal_fee(&admin, &usdc.address, &5000);
+    mock_usdc_pool.set_b_rate(&1_100_000_000_000);
+    assert!(vaquita.try_withdraw(&alice, &eurc.address, &String::from_str(&e, "alice")).is_err());
+    vaquita.withdraw(&alice, &usdc.address, &String::from_str(&e, "alice"));
+    assert_eq!(usdc.balance(&alice), 100_0000000);
+    assert_eq!(vaquita.get_protocol_fees(&usdc.address), 5_0000000);
+    assert_eq!(vaquita.get_protocol_fees(&eurc.address), 0);
+
+    e.jump_time(3 * WEEK);
+    vaquita.withdraw(&bob, &eurc.address, &String::from_str(&e, "bob"));
+    assert_eq!(eurc.balance(&bob), 200_0000000);
+    assert_eq!(vaquita.get_total_b_tokens(&eurc.address), 0);
+}
+
+#[test]
+fn tokens_sharing_a_blend_pool_keep_their_own_reserves() {
+    let e = Env::default();
+    let VaquitaFixture { admin, usdc, mock_pool, vaquita } = VaquitaFixture::deploy(&e, SCALAR_12, &[WEEK]);
+    let pool = mock_pool.address.clone();
+    let alice = Address::generate(&e);
+    let bob = Address::generate(&e);
+    let eurc = MockTokenClient::new(&e, &e.register_stellar_asset_contract_v2(admin.clone()).address());
+    usdc.mint(&alice, &100_0000000);
+    eurc.mint(&bob, &50_0000000);
+
+    vaquita.add_token(&admin, &eurc.address, &pool, &Vec::from_array(&e, [WEEK]));
+    vaquita.update_rebalance_config(&admin, &usdc.address, &100_0000000, &10, &0, &0);
+    assert!(vaquita.get_rebalance_config(&usdc.address).is_some());
+    assert!(vaquita.get_rebalance_config(&eurc.address).is_none());
+
+    // Each token is its own reserve of the pool
+    vaquita.deposit(&alice, &usdc.address, &String::from_str(&e, "alice"), &100_0000000, &WEEK);
+    vaquita.deposit(&bob, &eurc.address, &String::from_str(&e, "bob"), &50_0000000, &WEEK);
+    let supply = mock_pool.get_positions(&vaquita.address).supply;
+    assert_eq!((supply.get(0), supply.get(1)), (Some(100_0000000), Some(50_0000000)));
+    assert_eq!(vaquita.get_position(&String::from_str(&e, "alice")).unwrap().b_tokens, 100_0000000);
+    assert_eq!(vaquita.get_position(&String::from_str(&e, "bob")).unwrap().b_tokens, 50_0000000);
+
+    e.jump_time(2 * WEEK);
+    vaquita.withdraw(&bob, &eurc.address, &String::from_str(&e, "bob"));
+    assert_eq!(eurc.balance(&bob), 50_0000000);
+    assert_eq!(mock_pool.get_positions(&vaquita.address).supply.get(0), Some(100_0000000));
+    vaquita.withdraw(&alice, &usdc.address, &String::from_str(&e, "alice"));
+    assert_eq!(usdc.balance(&alice), 100_0000000);
+    assert_eq!(vaquita.get_total_b_tokens(&usdc.address), 0);
+    assert_eq!(vaquita.get_total_b_tokens(&eurc.address), 0);
+}
@@ -0,0 +1,304 @@
+use soroban_fixed_point_math::SorobanFixedPoint;
+use soroban_sdk::{contracttype, token::Client as TokenClient, Address, Env, Vec};
+
+use crate::{BlendPoolClient, DataKey, Request, VaquitaPoolError, SCALAR_12, SCALAR_7};
+
+// Blend request types
+const SUPPLY: u32 = 0;
+const WITHDRAW: u32 = 1;
+
+// Highest Blend pool status accepting supply, "active" set by the admin or the backstop
+const ACTIVE: u32 = 1;
+
+// Utilization above which Blend applies `r_three`, scaled by SCALAR_7
+const MAX_TARGET_UTIL: i128 = 9_500_000;
+
+/// Market the contract supplies deposits to. Positions hold shares of it (bTokens
+/// on Blend), which `rate` converts to the deposit token.
+pub trait YieldSource {
+    /// Supplies `amount` of the deposit token held by the contract and returns the
+    /// shares minted for it.
+    fn supply(&self, env: &Env, amount: i128) -> i128;
+
+    /// Withdraws `amount` of the deposit token to the contract and returns the shares
+    /// burned for it, or None when the market lacks the liquidity.
+    fn withdraw(&self, env: &Env, amount: i128) -> Option<i128>;
+
+    /// Deposit token per share, scaled by SCALAR_12.
+    fn rate(&self, env: &Env) -> i128;
+
+    /// Shares the contract holds.
+    fn balance(&self, env: &Env) -> i128;
+
+    /// Annual rate suppliers currently earn, scaled by SCALAR_7.
+    fn supply_rate(&self, env: &Env) -> i128;
+
+    /// Whether the market is healthy enough to be supplied `amount` more.
+    fn check_supply(&self, env: &Env, amount: i128) -> Result<(), VaquitaPoolError>;
+
+    /// Whether the market is on ice or frozen, and funds should leave it.
+    fn is_degraded(&self, env: &Env) -> bool;
+
+    /// Current value in the deposit token of `shares`, rounded down.
+    fn value(&self, env: &Env, shares: i128) -> i128 {
+        if shares <= 0 {
+            return 0;
+        }
+        shares.fixed_mul_floor(env, &self.rate(env), &SCALAR_12)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum YieldSourceKind {
+    Blend,
+    Vault,
+}
+
+/// Supplies a deposit token to a Blend pool: its `DataKey::PoolAddress`, or one of its
+/// allocation pools.
+pub struct BlendSource {
+    pool: Address,
+    token: Address,
+}
+
+impl BlendSource {
+    fn client<'a>(&self, env: &'a Env) -> BlendPoolClient<'a> {
+        BlendPoolClient::new(env, &self.pool)
+    }
+
+    fn reserve_index(&self, env: &Env) -> u32 {
+        self.client(env).get_reserve(&self.token).config.index
+    }
+
+    fn b_tokens(&self, env: &Env, reserve_index: u32) -> i128 {
+        self.client(env)
+            .get_positions(&env.current_contract_address())
+            .supply
+            .get(reserve_index)
+            .unwrap_or(0)
+    }
+
+    fn requests(&self, env: &Env, request_type: u32, amount: i128) -> Vec<Request> {
+        let request = Request {
+            request_type,
+            address: self.token.clone(),
+            amount,
+        };
+        Vec::from_array(env, [request])
+    }
+}
+
+impl YieldSource for BlendSource {
+    fn supply(&self, env: &Env, amount: i128) -> i128 {
+        let contract_address = env.current_contract_address();
+        let reserve_index = self.reserve_index(env);
+        let b_tokens_before = self.b_tokens(env, reserve_index);
+        TokenClient::new(env, &self.token).approve(
+            &contract_address,
+            &self.pool,
+            &amount,
+            &env.ledger().sequence(),
+        );
+        let requests = self.requests(env, SUPPLY, amount);
+        self.client(env).submit_with_allowance(&contract_address, &contract_address, &contract_address, &requests);
+        self.b_tokens(env, reserve_index) - b_tokens_before
+    }
+
+    fn withdraw(&self, env: &Env, amount: i128) -> Option<i128> {
+        if amount <= 0 {
+            return Some(0);
+        }
+        let contract_address = env.current_contract_address();
+        let reserve_index = self.reserve_index(env);
+        let b_tokens_before = self.b_tokens(env, reserve_index);
+        let requests = self.requests(env, WITHDRAW, amount);
+        if self.client(env).try_submit(&contract_address, &contract_address, &contract_address, &requests).is_err() {
+            return None;
+        }
+        Some(b_tokens_before - self.b_tokens(env, reserve_index))
+    }
+
+    fn rate(&self, env: &Env) -> i128 {
+        self.client(env).get_reserve(&self.token).data.b_rate
+    }
+
+    fn balance(&self, env: &Env) -> i128 {
+        self.b_tokens(env, self.reserve_index(env))
+    }
+
+    /// Blend's borrow rate curve at the reserve's current utilization, scaled by
+    /// `ir_mod`, passed on to suppliers net of the backstop's take.
+    fn supply_rate(&self, env: &Env) -> i128 {
+        let reserve = self.client(env).get_reserve(&self.token);
+        let supplied = reserve.data.b_supply.fixed_mul_floor(env, &reserve.data.b_rate, &SCALAR_12);
+        let borrowed = reserve.data.d_supply.fixed_mul_floor(env, &reserve.data.d_rate, &SCALAR_12);
+        if supplied <= 0 || borrowed <= 0 {
+            return 0;
+        }
+        let util = borrowed.fixed_div_floor(env, &supplied, &SCALAR_7).min(SCALAR_7);
+
+        let config = reserve.config;
+        let target_util = config.util as i128;
+        let (r_base, r_one, r_two, r_three) =
+            (config.r_base as i128, config.r_one as i128, config.r_two as i128, config.r_three as i128);
+        let ir_mod = reserve.data.ir_mod;
+        let borrow_rate = if util <= target_util {
+            let util_scalar = util.fixed_div_floor(env, &target_util, &SCALAR_7);
+            (util_scalar.fixed_mul_floor(env, &r_one, &SCALAR_7) + r_base).fixed_mul_floor(env, &ir_mod, &SCALAR_7)
+        } else if util <= MAX_TARGET_UTIL {
+            let util_scalar = (util - target_util).fixed_div_floor(env, &(MAX_TARGET_UTIL - target_util), &SCALAR_7);
+            (util_scalar.fixed_mul_floor(env, &r_two, &SCALAR_7) + r_one + r_base).fixed_mul_floor(env, &ir_mod, &SCALAR_7)
+        } else {
+            let util_scalar = (util - MAX_TARGET_UTIL).fixed_div_floor(env, &(SCALAR_7 - MAX_TARGET_UTIL), &SCALAR_7);
+            util_scalar.fixed_mul_floor(env, &r_three, &SCALAR_7)
+                + (r_two + r_one + r_base).fixed_mul_floor(env, &ir_mod, &SCALAR_7)
+        };
+
+        let bstop_rate = self.client(env).get_config().bstop_rate as i128;
+        borrow_rate
+            .fixed_mul_floor(env, &util, &SCALAR_7)
+            .fixed_mul_floor(env, &(SCALAR_7 - bstop_rate), &SCALAR_7)
+    }
+
+    fn check_supply(&self, env: &Env, amount: i128) -> Result<(), VaquitaPoolError> {
+        if self.is_degraded(env) {
+            return Err(VaquitaPoolError::PoolUnhealthy);
+        }
+        let reserve = self.client(env).get_reserve(&self.token);
+        if !reserve.config.enabled {
+            return Err(VaquitaPoolError::ReserveDisabled);
+        }
+        let supplied = reserve.data.b_supply.fixed_mul_ceil(env, &reserve.data.b_rate, &SCALAR_12);
+        if supplied + amount > reserve.config.supply_cap {
+            return Err(VaquitaPoolError::SupplyCapReached);
+        }
+        Ok(())
+    }
+
+    fn is_degraded(&self, env: &Env) -> bool {
+        self.client(env).get_config().status > ACTIVE
+    }
+}
+
+/// Keeps deposits idle in the contract without earning yield, one share per token.
+pub struct VaultSource {
+    token: Address,
+}
+
+impl VaultSource {
+    fn shares(&self, env: &Env) -> i128 {
+        env.storage().instance().get(&DataKey::VaultShares(self.token.clone())).unwrap_or(0)
+    }
+}
+
+impl YieldSource for VaultSource {
+    fn supply(&self, env: &Env, amount: i128) -> i128 {
+        env.storage().instance().set(&DataKey::VaultShares(self.token.clone()), &(self.shares(env) + amount));
+        amount
+    }
+
+    fn withdraw(&self, env: &Env, amount: i128) -> Option<i128> {
+        let shares = self.shares(env);
+        if amount > shares {
+            return None;
+        }
+        env.storage().instance().set(&DataKey::VaultShares(self.token.clone()), &(shares - amount));
+        Some(amount)
+    }
+
+    fn rate(&self, _env: &Env) -> i128 {
+        SCALAR_12
+    }
+
+    fn balance(&self, env: &Env) -> i128 {
+        self.shares(env)
+    }
+
+    fn supply_rate(&self, _env: &Env) -> i128 {
+        0
+    }
+
+    fn check_supply(&self, _env: &Env, _amount: i128) -> Result<(), VaquitaPoolError> {
+        Ok(())
+    }
+
+    fn is_degraded(&self, _env: &Env) -> bool {
+        false
+    }
+}
+
+/// Yield source `DataKey::YieldSource` selects for a deposit token, Blend unless
+/// configured otherwise.
+pub enum Adapter {
+    Blend(BlendSource),
+    Vault(VaultSource),
+}
+
+impl Adapter {
+    pub fn load(env: &Env, token: &Address) -> Self {
+        let kind: YieldSourceKind = env.storage().instance()
+            .get(&DataKey::YieldSource(token.clone()))
+            .unwrap_or(YieldSourceKind::Blend);
+        match kind {
+            YieldSourceKind::Blend => Adapter::Blend(BlendSource {
+                pool: env.storage().instance().get(&DataKey::PoolAddress(token.clone())).unwrap(),
+                token: token.clone(),
+            }),
+            YieldSourceKind::Vault => Adapter::Vault(VaultSource { token: token.clone() }),
+        }
+    }
+
+    /// The `token` reserve of the Blend pool `pool`, regardless of the configured yield source.
+    pub fn blend(token: &Address, pool: Address) -> Self {
+        Adapter::Blend(BlendSource {
+            pool,
+            token: token.clone(),
+        })
+    }
+
+    /// Source of a position: its allocation pool if it has one, else the configured one.
+    pub fn for_pool(env: &Env, token: &Address, pool: &Option<Address>) -> Self {
+        match pool {
+            Some(pool) => Self::blend(token, pool.clone()),
+            None => Self::load(env, token),
+        }
+    }
+
+    fn source(&self) -> &dyn YieldSource {
+        match self {
+            Adapter::Blend(source) => source,
+            Adapter::Vault(source) => source,
+        }
+    }
+}
+
+impl YieldSource for Adapter {
+    fn supply(&self, env: &Env, amount: i128) -> i128 {
+        self.source().supply(env, amount)
+    }
+
+    fn withdraw(&self, env: &Env, amount: i128) -> Option<i128> {
+        self.source().withdraw(env, amount)
+    }
+
+    fn rate(&self, env: &Env) -> i128 {
+        self.source().rate(env)
+    }
+
+    fn balance(&self, env: &Env) -> i128 {
+        self.source().balance(env)
+    }
+
+    fn supply_rate(&self, env: &Env) -> i128 {
+        self.source().supply_rate(env)
+    }
+
+    fn check_supply(&self, env: &Env, amount: i128) -> Result<(), VaquitaPoolError> {
+        self.source().check_supply(env, amount)
+    }
+
+    fn is_degraded(&self, env: &Env) -> bool {
+        self.source().is_degraded(env)
+    }
+}